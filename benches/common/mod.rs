@@ -0,0 +1,27 @@
+// Items and trees shared by the benchmark targets.
+use completion_trie::{Completable, CompletionTree, Key, KeyKind};
+use rand::{distributions::Alphanumeric, distributions::DistString, prelude::SmallRng, Rng};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BenchItem(pub String, pub i32);
+impl Completable for BenchItem {
+    fn keys(&self) -> Vec<Key> {
+        vec![Key {
+            symbols: self.0.as_bytes().to_owned(),
+            score: self.1,
+            kind: KeyKind::Primary,
+        }]
+    }
+}
+pub fn make_random_item(prng: &mut SmallRng) -> BenchItem {
+    let name = Alphanumeric.sample_string(prng, 30);
+    let score = prng.gen_range(0..1_000);
+    BenchItem(name, score)
+}
+pub fn make_random_tree(prng: &mut SmallRng, n: usize) -> CompletionTree<BenchItem> {
+    let mut tree = CompletionTree::default();
+    for _ in 0..n {
+        tree.put(make_random_item(prng));
+    }
+    tree
+}
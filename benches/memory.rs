@@ -0,0 +1,41 @@
+mod common;
+
+use common::make_random_tree;
+use rand::{prelude::SmallRng, SeedableRng};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// Tracks live heap bytes so that we can report how much memory a tree holds on to. It lives in its
+// own target so that the timing benchmarks keep the system allocator.
+struct CountingAllocator;
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn main() {
+    for tree_size in [1_000, 10_000, 100_000] {
+        let mut prng = SmallRng::seed_from_u64(42);
+        let before = LIVE_BYTES.load(Ordering::Relaxed);
+        let tree = make_random_tree(&mut prng, tree_size);
+        let retained = LIVE_BYTES.load(Ordering::Relaxed) - before;
+        println!(
+            "memory/{}-tree: {} bytes ({} per item)",
+            tree_size,
+            retained,
+            retained / tree_size
+        );
+        drop(tree);
+    }
+}
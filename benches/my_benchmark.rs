@@ -1,36 +1,67 @@
-use completion_trie::{Completable, CompletionTree, Key};
+mod common;
+
+use common::{make_random_item, make_random_tree, BenchItem};
+use completion_trie::{CompletionTree, CompletionTreeBuilder};
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-use rand::{
-    distributions::Alphanumeric, distributions::DistString, prelude::SmallRng, Rng, SeedableRng,
-};
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct BenchItem(String, i32);
-impl Completable for BenchItem {
-    fn keys(&self) -> Vec<Key> {
-        vec![Key {
-            bytes: self.0.as_bytes().to_owned(),
-            score: self.1,
-        }]
+use rand::{prelude::SmallRng, SeedableRng};
+
+fn criterion_benchmark(c: &mut Criterion) {
+    for tree_size in [1_000, 10_000] {
+        c.bench_function(&format!("construct {}k", tree_size / 1_000), |b| {
+            b.iter(|| {
+                let mut prng = SmallRng::seed_from_u64(42);
+                black_box(make_random_tree(&mut prng, tree_size))
+            })
+        });
     }
-}
-fn make_random_tree(prng: &mut SmallRng, n: usize) -> CompletionTree<BenchItem> {
-    let mut tree = CompletionTree::default();
-    for _ in 0..n {
-        let name = Alphanumeric.sample_string(prng, 30);
-        let score = prng.gen_range(0..1_000);
-        tree.put(BenchItem(name, score));
+
+    for tree_size in [1_000, 10_000] {
+        c.bench_function(&format!("collect {}k", tree_size / 1_000), |b| {
+            b.iter(|| {
+                let mut prng = SmallRng::seed_from_u64(42);
+                black_box(
+                    (0..tree_size)
+                        .map(|_| make_random_item(&mut prng))
+                        .collect::<CompletionTree<_>>(),
+                )
+            })
+        });
+        #[cfg(feature = "rayon")]
+        c.bench_function(&format!("par_from_iter {}k", tree_size / 1_000), |b| {
+            b.iter(|| {
+                let mut prng = SmallRng::seed_from_u64(42);
+                let items: Vec<_> = (0..tree_size)
+                    .map(|_| make_random_item(&mut prng))
+                    .collect();
+                black_box(CompletionTree::par_from_iter(items))
+            })
+        });
+        c.bench_function(&format!("build frozen {}k", tree_size / 1_000), |b| {
+            b.iter(|| {
+                let mut prng = SmallRng::seed_from_u64(42);
+                let mut builder = CompletionTreeBuilder::new();
+                builder.extend((0..tree_size).map(|_| make_random_item(&mut prng)));
+                black_box(builder.build())
+            })
+        });
     }
-    tree
-}
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("construct 1k", |b| {
+    // Zero-padded counters share long prefixes, so most of each descent is shared with its neighbours.
+    let counters: Vec<BenchItem> = (0..10_000)
+        .map(|i| BenchItem(format!("{:012}", i), i % 1_000))
+        .collect();
+    c.bench_function("construct 10k counters", |b| {
         b.iter(|| {
-            let mut prng = SmallRng::seed_from_u64(42);
-            black_box(make_random_tree(&mut prng, 1_000))
+            let mut tree = CompletionTree::default();
+            for item in counters.iter().cloned() {
+                tree.put(item);
+            }
+            black_box(tree)
         })
     });
+    c.bench_function("collect 10k counters", |b| {
+        b.iter(|| black_box(counters.iter().cloned().collect::<CompletionTree<_>>()))
+    });
 
     let query = b"blahblahgarbage";
     for query_len in [0, 1, query.len()] {
@@ -44,5 +75,27 @@ fn criterion_benchmark(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, criterion_benchmark);
+fn top_k_benchmark(c: &mut Criterion) {
+    // The shallow levels of a random 100k-tree fan out across the whole alphanumeric alphabet.
+    let mut prng = SmallRng::seed_from_u64(42);
+    let tree = make_random_tree(&mut prng, 100_000);
+    for k in [10, 100] {
+        c.bench_function(&format!("take({}) on 100000-tree", k), |b| {
+            b.iter(|| black_box(tree.search(b"").take(k).collect::<Vec<_>>()))
+        });
+        c.bench_function(&format!("top_k({}) on 100000-tree", k), |b| {
+            b.iter(|| black_box(tree.top_k(b"", k)))
+        });
+    }
+
+    let mut prng = SmallRng::seed_from_u64(42);
+    let mut builder = CompletionTreeBuilder::new();
+    builder.extend((0..100_000).map(|_| make_random_item(&mut prng)));
+    let frozen = builder.build();
+    c.bench_function("take(10) on 100000-frozen-tree", |b| {
+        b.iter(|| black_box(frozen.search(b"").take(10).collect::<Vec<_>>()))
+    });
+}
+
+criterion_group!(benches, criterion_benchmark, top_k_benchmark);
 criterion_main!(benches);
@@ -0,0 +1,152 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Error, Index, LitStr, Member, Type};
+
+/// Implements `Completable` from `#[complete(...)]` attributes on a struct's fields:
+///
+/// ```text
+/// #[complete(key)]                      the field's text is a key
+/// #[complete(key, tokenize = "words")]  so is every suffix that starts a word
+/// #[complete(alias)]                    the field's text is another name for the item
+/// #[complete(score)]                    the score every key gets
+/// ```
+///
+/// A field may carry several `key` attributes, one per way of tokenizing it. Tokenizers are named
+/// after the generators in `completion_trie::keys`: "exact" (the default), "words", "identifier",
+/// "path" and "initials". At least one field must be a key or an alias. Without a `score` field,
+/// every key scores 0.
+///
+/// Keys are spelled in bytes unless the struct itself is marked `#[complete(alphabet = "char")]`.
+#[proc_macro_derive(Completable, attributes(complete))]
+pub fn derive_completable(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                input,
+                "Completable can only be derived for structs",
+            ))
+        }
+    };
+    let alphabet = alphabet(input)?;
+    let mut score: Option<(Member, &Type)> = None;
+    let mut keys = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(index)),
+        };
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("complete")) {
+            let (mut is_key, mut is_alias, mut is_score) = (false, false, false);
+            let mut tokenize = None;
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("key") {
+                    is_key = true;
+                } else if meta.path.is_ident("alias") {
+                    is_alias = true;
+                } else if meta.path.is_ident("score") {
+                    is_score = true;
+                } else if meta.path.is_ident("tokenize") {
+                    tokenize = Some(meta.value()?.parse::<LitStr>()?);
+                } else {
+                    return Err(meta.error("expected `key`, `alias`, `score` or `tokenize`"));
+                }
+                Ok(())
+            })?;
+            match (is_key, is_alias, is_score) {
+                (true, false, false) => keys.push(key(&member, tokenize.as_ref())?),
+                (false, _, _) if tokenize.is_some() => {
+                    return Err(Error::new_spanned(attr, "only keys can be tokenized"))
+                }
+                (false, true, false) => keys.push(alias(&member)),
+                (false, false, true) if score.is_some() => {
+                    return Err(Error::new_spanned(attr, "only one field can be the score"))
+                }
+                (false, false, true) => score = Some((member.clone(), &field.ty)),
+                _ => {
+                    return Err(Error::new_spanned(
+                        attr,
+                        "expected exactly one of `key`, `alias` or `score`",
+                    ))
+                }
+            }
+        }
+    }
+
+    if keys.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Completable needs at least one `#[complete(key)]` or `#[complete(alias)]` field",
+        ));
+    }
+    let (score, score_type) = match score {
+        Some((member, ty)) => (quote!(self.#member), quote!(#ty)),
+        None => (quote!(0), quote!(i32)),
+    };
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::completion_trie::Completable<#score_type, #alphabet>
+            for #name #type_generics
+        #where_clause
+        {
+            fn keys(&self) -> ::std::vec::Vec<::completion_trie::Key<#score_type, #alphabet>> {
+                ::completion_trie::keys::Keys::<#score_type, #alphabet>::new(#score)
+                    #(#keys)*
+                    .build()
+            }
+        }
+    })
+}
+
+fn alphabet(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let mut alphabet = quote!(u8);
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("complete")) {
+        attr.parse_nested_meta(|meta| {
+            if !meta.path.is_ident("alphabet") {
+                return Err(meta.error("expected `alphabet`"));
+            }
+            let value = meta.value()?.parse::<LitStr>()?;
+            alphabet = match value.value().as_str() {
+                "u8" => quote!(u8),
+                "char" => quote!(char),
+                _ => return Err(Error::new_spanned(value, "expected \"u8\" or \"char\"")),
+            };
+            Ok(())
+        })?;
+    }
+    Ok(alphabet)
+}
+
+fn text(member: &Member) -> TokenStream2 {
+    quote!(::core::convert::AsRef::<str>::as_ref(&self.#member))
+}
+
+fn alias(member: &Member) -> TokenStream2 {
+    let text = text(member);
+    quote!(.alias(#text))
+}
+
+fn key(member: &Member, tokenize: Option<&LitStr>) -> Result<TokenStream2, Error> {
+    let text = text(member);
+    const EXPECTED: &str =
+        "expected \"exact\", \"words\", \"identifier\", \"path\" or \"initials\"";
+    let generator = match tokenize.map(LitStr::value).as_deref() {
+        None | Some("exact") => return Ok(quote!(.exact(#text))),
+        Some("words") => "word_suffixes",
+        Some("identifier") => "identifier_suffixes",
+        Some("path") => "path_suffixes",
+        Some("initials") => "initials",
+        Some(_) => return Err(Error::new_spanned(tokenize, EXPECTED)),
+    };
+    let generator = format_ident!("{}", generator);
+    Ok(quote!(.#generator(#text, |score| score)))
+}
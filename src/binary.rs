@@ -0,0 +1,610 @@
+use crate::{Completable, CompletionTree, Items, Node, Scored, Symbol, TotalOrd};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    fmt,
+    io::{self, Read, Write},
+    sync::Arc,
+};
+
+// Layout, with every integer little-endian and every offset relative to the start of the body:
+//
+//   header   magic, version, reserved, body length, nodes offset, root offset, then a checksum of
+//            everything else in the file
+//   items    count, then count + 1 offsets delimiting each encoded item, then the items themselves
+//   nodes    post-order, so that a node's children always sit at smaller offsets than it does
+//
+// A node is its max score, its label, its items as (item index, score) pairs, and the offsets of
+// its children in label order.
+const MAGIC: &[u8; 8] = b"CMPLTRIE";
+const VERSION: u32 = 1;
+pub(crate) const HEADER_LEN: usize = 48;
+pub(crate) const NO_ROOT: u64 = u64::MAX;
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u32),
+    Truncated,
+    ChecksumMismatch,
+    Corrupt(&'static str),
+}
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormatError::Io(err) => write!(f, "i/o error: {}", err),
+            FormatError::BadMagic => write!(f, "not a completion tree"),
+            FormatError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            FormatError::Truncated => write!(f, "unexpected end of input"),
+            FormatError::ChecksumMismatch => write!(f, "checksum mismatch"),
+            FormatError::Corrupt(what) => write!(f, "corrupt input: {}", what),
+        }
+    }
+}
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+impl From<io::Error> for FormatError {
+    fn from(err: io::Error) -> Self {
+        FormatError::Io(err)
+    }
+}
+
+/// How items, scores and symbols are written to and read back from the binary format. `decode`
+/// consumes what it reads from the front of `input`.
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+    fn decode(input: &mut &[u8]) -> Result<Self, FormatError>;
+}
+
+pub(crate) fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], FormatError> {
+    if input.len() < n {
+        return Err(FormatError::Truncated);
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_codec_for_int {
+    ($($int:ty),*) => {$(
+        impl Codec for $int {
+            fn encode(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_le_bytes());
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+                let bytes = take(input, std::mem::size_of::<$int>())?;
+                Ok(<$int>::from_le_bytes(bytes.try_into().expect("sized by take")))
+            }
+        }
+    )*};
+}
+impl_codec_for_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+macro_rules! impl_codec_for_float {
+    ($($float:ty),*) => {$(
+        impl Codec for TotalOrd<$float> {
+            fn encode(&self, out: &mut Vec<u8>) {
+                self.0.to_bits().encode(out);
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+                Codec::decode(input).map(|bits| TotalOrd(<$float>::from_bits(bits)))
+            }
+        }
+    )*};
+}
+impl_codec_for_float!(f32, f64);
+
+impl Codec for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        u32::from(*self).encode(out);
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+        char::from_u32(u32::decode(input)?).ok_or(FormatError::Corrupt("invalid char"))
+    }
+}
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| FormatError::Corrupt("invalid utf-8"))
+    }
+}
+impl<C: Codec> Codec for Vec<C> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for c in self {
+            c.encode(out);
+        }
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+        let len = decode_len(input)?;
+        // Don't trust the length for preallocation; a corrupt one could be enormous.
+        let mut buf = Vec::with_capacity(std::cmp::min(len, input.len()));
+        for _ in 0..len {
+            buf.push(C::decode(input)?);
+        }
+        Ok(buf)
+    }
+}
+macro_rules! impl_codec_for_tuple {
+    ($($name:ident),*) => {
+        impl<$($name: Codec),*> Codec for ($($name,)*) {
+            #[allow(non_snake_case)]
+            fn encode(&self, out: &mut Vec<u8>) {
+                let ($($name,)*) = self;
+                $($name.encode(out);)*
+            }
+            fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+                Ok(($($name::decode(input)?,)*))
+            }
+        }
+    };
+}
+impl_codec_for_tuple!(C0, C1);
+impl_codec_for_tuple!(C0, C1, C2);
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    u32::try_from(len)
+        .expect("lengths fit in a u32")
+        .encode(out);
+}
+fn decode_len(input: &mut &[u8]) -> Result<usize, FormatError> {
+    Ok(u32::decode(input)? as usize)
+}
+fn decode_offset(input: &mut &[u8]) -> Result<usize, FormatError> {
+    usize::try_from(u64::decode(input)?).map_err(|_| FormatError::Corrupt("offset out of range"))
+}
+
+// FNV-1a: not cryptographic, but cheap and plenty to catch truncation and bit rot.
+fn checksum(header: &[u8], body: &[u8]) -> u64 {
+    header
+        .iter()
+        .chain(body)
+        .fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+            (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+        })
+}
+
+pub(crate) struct Header {
+    pub nodes_offset: usize,
+    pub root_offset: Option<usize>,
+}
+
+// Checks everything that can be checked without understanding the body, and returns the body.
+pub(crate) fn parse_header(bytes: &[u8]) -> Result<(Header, &[u8]), FormatError> {
+    let mut input = bytes;
+    if take(&mut input, MAGIC.len()).map_err(|_| FormatError::BadMagic)? != MAGIC {
+        return Err(FormatError::BadMagic);
+    }
+    let version = u32::decode(&mut input)?;
+    if version != VERSION {
+        return Err(FormatError::UnsupportedVersion(version));
+    }
+    if u32::decode(&mut input)? != 0 {
+        return Err(FormatError::Corrupt("reserved header bits set"));
+    }
+    let body_len = decode_offset(&mut input)?;
+    let nodes_offset = decode_offset(&mut input)?;
+    let root_offset = u64::decode(&mut input)?;
+    let expected_checksum = u64::decode(&mut input)?;
+    let body = &bytes[HEADER_LEN..];
+    if body.len() < body_len {
+        return Err(FormatError::Truncated);
+    }
+    if body.len() > body_len {
+        return Err(FormatError::Corrupt("trailing bytes"));
+    }
+    if checksum(&bytes[..HEADER_LEN - 8], body) != expected_checksum {
+        return Err(FormatError::ChecksumMismatch);
+    }
+    if nodes_offset > body.len() {
+        return Err(FormatError::Corrupt("nodes offset out of range"));
+    }
+    let root_offset = match root_offset {
+        NO_ROOT => None,
+        offset => {
+            Some(usize::try_from(offset).map_err(|_| FormatError::Corrupt("offset out of range"))?)
+        }
+    };
+    let header = Header {
+        nodes_offset,
+        root_offset,
+    };
+    Ok((header, body))
+}
+
+pub(crate) fn item_count(body: &[u8]) -> Result<usize, FormatError> {
+    decode_len(&mut &body[..])
+}
+
+// The encoded bytes of a single item, read straight out of the item table.
+pub(crate) fn item_bytes(body: &[u8], index: usize) -> Result<&[u8], FormatError> {
+    if index >= item_count(body)? {
+        return Err(FormatError::Corrupt("item index out of range"));
+    }
+    let mut offsets = body.get(4 + 8 * index..).ok_or(FormatError::Truncated)?;
+    let start = decode_offset(&mut offsets)?;
+    let end = decode_offset(&mut offsets)?;
+    body.get(start..end)
+        .ok_or(FormatError::Corrupt("item offset out of range"))
+}
+
+// A node as laid out on disk, read in place. Only the max score is decoded up front; the label,
+// items and children stay encoded until they are asked for.
+pub(crate) struct NodeView<'a, S> {
+    pub max_score: S,
+    // The end of the node, for readers walking the nodes section in order.
+    pub rest: &'a [u8],
+    label: &'a [u8],
+    label_len: usize,
+    items: &'a [u8],
+    item_count: usize,
+    children: &'a [u8],
+}
+impl<'a, S: Codec> NodeView<'a, S> {
+    pub(crate) fn parse<A: Codec>(mut input: &'a [u8]) -> Result<Self, FormatError> {
+        let max_score = S::decode(&mut input)?;
+        let label_len = decode_len(&mut input)?;
+        let label = skip(&mut input, label_len, |input| A::decode(input).map(drop))?;
+        let item_count = decode_len(&mut input)?;
+        let items = skip(&mut input, item_count, |input| {
+            u32::decode(input)?;
+            S::decode(input).map(drop)
+        })?;
+        let child_count = decode_len(&mut input)?;
+        let children = take(&mut input, child_count.saturating_mul(8))?;
+        Ok(Self {
+            max_score,
+            rest: input,
+            label,
+            label_len,
+            items,
+            item_count,
+            children,
+        })
+    }
+
+    pub(crate) fn label<A: Codec>(&self) -> impl Iterator<Item = Result<A, FormatError>> + 'a {
+        let mut input = self.label;
+        (0..self.label_len).map(move |_| A::decode(&mut input))
+    }
+
+    pub(crate) fn items(&self) -> impl Iterator<Item = Result<(usize, S), FormatError>> + 'a {
+        let mut input = self.items;
+        (0..self.item_count).map(move |_| Ok((decode_len(&mut input)?, S::decode(&mut input)?)))
+    }
+
+    pub(crate) fn children(&self) -> impl Iterator<Item = Result<usize, FormatError>> + 'a {
+        let mut input = self.children;
+        (0..self.children.len() / 8).map(move |_| decode_offset(&mut input))
+    }
+}
+
+// Steps over `count` values read by `read`, returning the bytes they occupied.
+fn skip<'a>(
+    input: &mut &'a [u8],
+    count: usize,
+    read: impl Fn(&mut &'a [u8]) -> Result<(), FormatError>,
+) -> Result<&'a [u8], FormatError> {
+    let start = *input;
+    for _ in 0..count {
+        read(input)?;
+    }
+    Ok(&start[..start.len() - input.len()])
+}
+
+impl<T, S, A> CompletionTree<T, S, A>
+where
+    T: Completable<S, A> + Codec,
+    S: Ord + Copy + Codec,
+    A: Symbol + Codec,
+{
+    pub fn to_bytes(&self) -> Vec<u8> {
+        // Items are numbered in the order the nodes first reach them, rather than by id, so that
+        // equal trees encode identically however their ids were handed out.
+        let mut indices = HashMap::new();
+        let mut items = Vec::new();
+        if let Some(root) = self.root.as_ref() {
+            index_items(root, &mut indices, &mut items);
+        }
+
+        let mut body = Vec::new();
+        encode_items(items.into_iter().map(|id| self.items.get(id)), &mut body);
+        let nodes_offset = body.len() as u64;
+        let root_offset = match self.root.as_ref() {
+            None => NO_ROOT,
+            Some(root) => write_node(root, &indices, &mut body),
+        };
+        seal(body, nodes_offset, root_offset)
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FormatError> {
+        let (header, body) = parse_header(bytes)?;
+        let items = (0..item_count(body)?)
+            .map(|index| {
+                let mut encoded = item_bytes(body, index)?;
+                let item = T::decode(&mut encoded)?;
+                if !encoded.is_empty() {
+                    return Err(FormatError::Corrupt("item has trailing bytes"));
+                }
+                Ok(item)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // Nodes are stored children-first, so a single forward pass can assemble every node from
+        // ones it has already seen. Each node must be claimed by exactly one parent.
+        let mut pending = HashMap::new();
+        let mut input = &body[header.nodes_offset..];
+        while !input.is_empty() {
+            let offset = body.len() - input.len();
+            let view = NodeView::<S>::parse::<A>(input)?;
+            input = view.rest;
+            let mut node = Node::new(view.label().collect::<Result<_, _>>()?, view.max_score);
+            for item in view.items() {
+                let (index, score) = item?;
+                if index >= items.len() {
+                    return Err(FormatError::Corrupt("item index out of range"));
+                }
+                node.items.push(Scored {
+                    item: index as u32,
+                    score,
+                });
+            }
+            for child in view.children() {
+                let child: Node<u32, S, A> = pending
+                    .remove(&child?)
+                    .ok_or(FormatError::Corrupt("dangling child offset"))?;
+                let first = *child
+                    .label
+                    .first()
+                    .ok_or(FormatError::Corrupt("empty child label"))?;
+                if node.children.insert(first, Arc::new(child)).is_some() {
+                    return Err(FormatError::Corrupt("duplicate child label"));
+                }
+            }
+            pending.insert(offset, node);
+        }
+        let root = match header.root_offset {
+            None => None,
+            Some(offset) => Some(Arc::new(
+                pending
+                    .remove(&offset)
+                    .ok_or(FormatError::Corrupt("dangling root offset"))?,
+            )),
+        };
+        if !pending.is_empty() {
+            return Err(FormatError::Corrupt("unreachable nodes"));
+        }
+        Ok(Self {
+            root,
+            items: Items::from_slots(items.into_iter().map(Some).collect()),
+            normalizer: None,
+            policy: None,
+        })
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, FormatError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+pub(crate) fn encode_items<'a, T: Codec + 'a>(
+    items: impl ExactSizeIterator<Item = &'a T>,
+    body: &mut Vec<u8>,
+) {
+    encode_len(items.len(), body);
+    let offsets_at = body.len();
+    body.resize(offsets_at + 8 * (items.len() + 1), 0);
+    let mut offsets = Vec::with_capacity(items.len() + 1);
+    for item in items {
+        offsets.push(body.len() as u64);
+        item.encode(body);
+    }
+    offsets.push(body.len() as u64);
+    for (i, offset) in offsets.into_iter().enumerate() {
+        let at = offsets_at + 8 * i;
+        body[at..at + 8].copy_from_slice(&offset.to_le_bytes());
+    }
+}
+
+// Prepends the header to a finished body.
+pub(crate) fn seal(body: Vec<u8>, nodes_offset: u64, root_offset: u64) -> Vec<u8> {
+    let mut out = Vec::with_capacity(HEADER_LEN + body.len());
+    out.extend_from_slice(MAGIC);
+    VERSION.encode(&mut out);
+    0u32.encode(&mut out);
+    (body.len() as u64).encode(&mut out);
+    nodes_offset.encode(&mut out);
+    root_offset.encode(&mut out);
+    checksum(&out, &body).encode(&mut out);
+    debug_assert_eq!(out.len(), HEADER_LEN);
+    out.extend_from_slice(&body);
+    out
+}
+
+fn index_items<S, A>(
+    node: &Node<u32, S, A>,
+    indices: &mut HashMap<u32, u32>,
+    items: &mut Vec<u32>,
+) {
+    for scored in &node.items {
+        indices.entry(scored.item).or_insert_with(|| {
+            items.push(scored.item);
+            (items.len() - 1) as u32
+        });
+    }
+    for child in node.children.values() {
+        index_items(child, indices, items);
+    }
+}
+
+fn write_node<S, A>(node: &Node<u32, S, A>, indices: &HashMap<u32, u32>, out: &mut Vec<u8>) -> u64
+where
+    S: Codec,
+    A: Codec,
+{
+    let children: Vec<u64> = node
+        .children
+        .values()
+        .map(|child| write_node(child, indices, out))
+        .collect();
+    let offset = out.len() as u64;
+    node.max_score.encode(out);
+    node.label.encode(out);
+    encode_len(node.items.len(), out);
+    for scored in &node.items {
+        indices[&scored.item].encode(out);
+        scored.score.encode(out);
+    }
+    encode_len(children.len(), out);
+    for child in children {
+        child.encode(out);
+    }
+    offset
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HEADER_LEN;
+    use crate::fixtures::{make_tree, node, raw_file, Named, PREFIXES};
+    use crate::{Codec, CompletionTree, FormatError};
+
+    #[test]
+    fn round_trip_preserves_search_results() {
+        let tree = make_tree();
+        let mut bytes = Vec::new();
+        tree.write_to(&mut bytes).unwrap();
+        let loaded = CompletionTree::<Named>::read_from(&bytes[..]).unwrap();
+        for prefix in PREFIXES {
+            assert_eq!(
+                loaded.search_scored(prefix).collect::<Vec<_>>(),
+                tree.search_scored(prefix).collect::<Vec<_>>()
+            );
+        }
+        assert_eq!(loaded.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn items_are_stored_once() {
+        let tree = make_tree();
+        let bytes = tree.to_bytes();
+        let item_count = u32::decode(&mut &bytes[HEADER_LEN..]).unwrap();
+        assert_eq!(item_count, 8);
+    }
+
+    #[test]
+    fn empty_trees_round_trip() {
+        let tree = CompletionTree::<Named>::default();
+        let loaded = CompletionTree::<Named>::from_bytes(&tree.to_bytes()).unwrap();
+        assert_eq!(loaded.search(b"").count(), 0);
+    }
+
+    #[test]
+    fn truncated_input_is_an_error() {
+        let bytes = make_tree().to_bytes();
+        for len in 0..bytes.len() {
+            assert!(CompletionTree::<Named>::from_bytes(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn corrupt_input_is_an_error() {
+        let bytes = make_tree().to_bytes();
+        for i in 0..bytes.len() {
+            let mut corrupt = bytes.clone();
+            corrupt[i] ^= 0x20;
+            assert!(CompletionTree::<Named>::from_bytes(&corrupt).is_err());
+        }
+
+        let mut corrupt = bytes.clone();
+        corrupt[0] = b'X';
+        assert!(matches!(
+            CompletionTree::<Named>::from_bytes(&corrupt),
+            Err(FormatError::BadMagic)
+        ));
+        let mut corrupt = bytes.clone();
+        corrupt[8] = 2;
+        assert!(matches!(
+            CompletionTree::<Named>::from_bytes(&corrupt),
+            Err(FormatError::UnsupportedVersion(2))
+        ));
+        let mut corrupt = bytes;
+        *corrupt.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            CompletionTree::<Named>::from_bytes(&corrupt),
+            Err(FormatError::ChecksumMismatch)
+        ));
+    }
+
+    fn corruption(bytes: &[u8]) -> &'static str {
+        match CompletionTree::<Named>::from_bytes(bytes) {
+            Err(FormatError::Corrupt(what)) => what,
+            Err(err) => panic!("expected a corrupt body, got {}", err),
+            Ok(_) => panic!("expected a corrupt body, but it loaded"),
+        }
+    }
+
+    #[test]
+    fn structurally_corrupt_bodies_are_errors() {
+        let items = [Named::new("ab", 3), Named::new("ac", 1)];
+        let valid = raw_file(
+            &items,
+            &[
+                node(b"b", vec![(0, 3)], vec![]),
+                node(b"c", vec![(1, 1)], vec![]),
+                node(b"a", vec![], vec![0, 1]),
+                node(b"", vec![], vec![2]),
+            ],
+        );
+        let tree = CompletionTree::<Named>::from_bytes(&valid).unwrap();
+        assert_eq!(
+            tree.search(b"a").collect::<Vec<_>>(),
+            [&items[0], &items[1]]
+        );
+
+        let leaf = |label| node(label, vec![(0, 3)], vec![]);
+        assert_eq!(
+            corruption(&raw_file(&items, &[node(b"", vec![], vec![7])])),
+            "dangling child offset"
+        );
+        assert_eq!(
+            corruption(&raw_file(
+                &items,
+                &[leaf(b"ab"), leaf(b"ac"), node(b"", vec![], vec![0, 1])]
+            )),
+            "duplicate child label"
+        );
+        assert_eq!(
+            corruption(&raw_file(&items, &[leaf(b"a"), node(b"", vec![], vec![])])),
+            "unreachable nodes"
+        );
+        assert_eq!(
+            corruption(&raw_file(&items, &[leaf(b""), node(b"", vec![], vec![0])])),
+            "empty child label"
+        );
+        assert_eq!(
+            corruption(&raw_file(&items, &[node(b"", vec![(2, 0)], vec![])])),
+            "item index out of range"
+        );
+        let mut trailing = valid;
+        trailing.push(0);
+        assert_eq!(corruption(&trailing), "trailing bytes");
+    }
+}
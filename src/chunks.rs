@@ -0,0 +1,213 @@
+use std::{iter::FromIterator, sync::Arc};
+
+const BITS: usize = 5;
+const WIDTH: usize = 1 << BITS;
+
+// A vector whose clones share its contents. It is stored as a tree of `Arc`'d nodes with `WIDTH`
+// entries each, all at the same depth, and a change copies only the nodes on the way to what it
+// changes, so both cloning and changing one value cost time independent of the length.
+pub(crate) struct Chunks<V> {
+    root: Arc<Chunk<V>>,
+    // The number of levels of branches above the leaves.
+    height: usize,
+    len: usize,
+}
+
+// A branch holds only children and a leaf holds only values.
+#[derive(Clone)]
+struct Chunk<V> {
+    children: Vec<Arc<Chunk<V>>>,
+    values: Vec<V>,
+}
+
+impl<V> Clone for Chunks<V> {
+    fn clone(&self) -> Self {
+        Self {
+            root: Arc::clone(&self.root),
+            height: self.height,
+            len: self.len,
+        }
+    }
+}
+impl<V> Default for Chunks<V> {
+    fn default() -> Self {
+        Self {
+            root: Arc::new(Chunk::default()),
+            height: 0,
+            len: 0,
+        }
+    }
+}
+impl<V> Default for Chunk<V> {
+    fn default() -> Self {
+        Self {
+            children: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<V> Chunks<V> {
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn get(&self, index: usize) -> Option<&V> {
+        if index >= self.len {
+            return None;
+        }
+        let mut chunk = &*self.root;
+        for level in (1..=self.height).rev() {
+            chunk = &chunk.children[(index >> (BITS * level)) % WIDTH];
+        }
+        chunk.values.get(index % WIDTH)
+    }
+
+    pub(crate) fn iter(&self) -> Iter<'_, V> {
+        Iter {
+            stack: vec![self.root.children.iter()],
+            values: self.root.values.iter(),
+        }
+    }
+}
+
+impl<V: Clone> Chunks<V> {
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut V> {
+        if index >= self.len {
+            return None;
+        }
+        let mut chunk = Arc::make_mut(&mut self.root);
+        for level in (1..=self.height).rev() {
+            chunk = Arc::make_mut(&mut chunk.children[(index >> (BITS * level)) % WIDTH]);
+        }
+        chunk.values.get_mut(index % WIDTH)
+    }
+
+    pub(crate) fn push(&mut self, value: V) {
+        if self.len == WIDTH << (BITS * self.height) {
+            let root = std::mem::take(&mut self.root);
+            self.root = Arc::new(Chunk {
+                children: vec![root],
+                values: Vec::new(),
+            });
+            self.height += 1;
+        }
+        let mut chunk = Arc::make_mut(&mut self.root);
+        for level in (1..=self.height).rev() {
+            if (self.len >> (BITS * level)) % WIDTH == chunk.children.len() {
+                chunk.children.push(Arc::default());
+            }
+            chunk = Arc::make_mut(chunk.children.last_mut().expect("just pushed if missing"));
+        }
+        chunk.values.push(value);
+        self.len += 1;
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<V> {
+        let value = Arc::make_mut(&mut self.root).pop()?;
+        self.len -= 1;
+        while self.height > 0 && self.root.children.len() == 1 {
+            self.root = Arc::clone(&self.root.children[0]);
+            self.height -= 1;
+        }
+        Some(value)
+    }
+}
+
+impl<V: Clone> Chunk<V> {
+    // Takes the last value, dropping any chunk that it leaves empty.
+    fn pop(&mut self) -> Option<V> {
+        let last = match self.children.last_mut() {
+            None => return self.values.pop(),
+            Some(last) => Arc::make_mut(last),
+        };
+        let value = last.pop();
+        if last.children.is_empty() && last.values.is_empty() {
+            self.children.pop();
+        }
+        value
+    }
+}
+
+impl<V> FromIterator<V> for Chunks<V>
+where
+    V: Clone,
+{
+    fn from_iter<I: IntoIterator<Item = V>>(iter: I) -> Self {
+        let mut chunks = Self::default();
+        for value in iter {
+            chunks.push(value);
+        }
+        chunks
+    }
+}
+
+pub(crate) struct Iter<'a, V> {
+    // The children still to visit at each level above the current leaf.
+    stack: Vec<std::slice::Iter<'a, Arc<Chunk<V>>>>,
+    values: std::slice::Iter<'a, V>,
+}
+impl<'a, V> Iterator for Iter<'a, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<&'a V> {
+        loop {
+            if let Some(value) = self.values.next() {
+                return Some(value);
+            }
+            match self.stack.last_mut()?.next() {
+                None => {
+                    self.stack.pop();
+                }
+                Some(chunk) => {
+                    self.stack.push(chunk.children.iter());
+                    self.values = chunk.values.iter();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chunks, WIDTH};
+    use std::sync::Arc;
+
+    #[test]
+    fn behaves_like_a_vec() {
+        let mut chunks = Chunks::default();
+        let mut vec = Vec::new();
+        for i in 0..3 * WIDTH * WIDTH {
+            chunks.push(i);
+            vec.push(i);
+        }
+        for i in (0..vec.len()).step_by(7) {
+            *chunks.get_mut(i).unwrap() += 1;
+            vec[i] += 1;
+        }
+        assert_eq!(chunks.get(vec.len()), None);
+        assert!(chunks.iter().eq(vec.iter()));
+        while let Some(i) = vec.pop() {
+            assert_eq!(chunks.pop(), Some(i));
+            assert_eq!(chunks.len(), vec.len());
+            assert_eq!(chunks.get(vec.len() / 2), vec.get(vec.len() / 2));
+        }
+        assert_eq!(chunks.pop(), None);
+        assert_eq!(chunks.height, 0);
+    }
+
+    #[test]
+    fn clones_share_what_neither_changes() {
+        let mut chunks: Chunks<_> = (0..WIDTH * WIDTH).collect();
+        let clone = chunks.clone();
+        *chunks.get_mut(0).unwrap() = 100;
+        assert_eq!(clone.get(0), Some(&0));
+        assert_eq!(chunks.get(0), Some(&100));
+        let (ours, theirs) = (&chunks.root.children, &clone.root.children);
+        assert!(!Arc::ptr_eq(&ours[0], &theirs[0]));
+        assert!(ours[1..]
+            .iter()
+            .zip(&theirs[1..])
+            .all(|(a, b)| Arc::ptr_eq(a, b)));
+    }
+}
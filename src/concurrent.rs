@@ -0,0 +1,138 @@
+use crate::{Completable, CompletionTree, Symbol};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// Shares a tree between many readers and a writer. Readers search an immutable snapshot, and
+/// writers build the next version off to the side and then swap it in, so that a search never
+/// waits on a write and never sees one half-applied.
+///
+/// The only lock a reader takes is held just long enough to clone the current `Arc`, which is
+/// also all the writer holds it for when publishing. Writes are serialized among themselves, and
+/// each one starts from a copy of the latest snapshot. Nodes and items are shared with that
+/// snapshot and only copied where a write changes them, so a write costs time proportional to
+/// what it changes rather than to the size of the tree.
+pub struct ConcurrentCompletionTree<T, S = i32, A = u8> {
+    current: RwLock<Arc<CompletionTree<T, S, A>>>,
+    writer: Mutex<()>,
+}
+impl<T, S, A> Default for ConcurrentCompletionTree<T, S, A> {
+    fn default() -> Self {
+        Self::from(CompletionTree::default())
+    }
+}
+impl<T, S, A> From<CompletionTree<T, S, A>> for ConcurrentCompletionTree<T, S, A> {
+    fn from(tree: CompletionTree<T, S, A>) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(tree)),
+            writer: Mutex::new(()),
+        }
+    }
+}
+impl<T, S, A> ConcurrentCompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A consistent view of the tree as of the last completed write. It is unaffected by later
+    /// writes, so every query made against it agrees with every other.
+    pub fn snapshot(&self) -> Arc<CompletionTree<T, S, A>> {
+        // A writer never panics while holding this lock, and a panicking writer only ever
+        // damages its own copy, so the shared snapshot is sound even when the lock is poisoned.
+        Arc::clone(&self.current.read().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Applies `f` to a copy of the latest snapshot and publishes the result once it returns.
+    /// Readers see either none of its changes or all of them. If `f` panics, nothing is
+    /// published and the tree stays as it was.
+    pub fn write<R>(&self, f: impl FnOnce(&mut CompletionTree<T, S, A>) -> R) -> R {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut tree = CompletionTree::clone(&self.snapshot());
+        let result = f(&mut tree);
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        let previous = std::mem::replace(&mut *current, Arc::new(tree));
+        drop(current);
+        // The previous version may be freed here, which readers have no reason to wait for.
+        drop(previous);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Completable, ConcurrentCompletionTree, Key, KeyKind};
+    use std::thread;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Pair(u32);
+    impl Completable for Pair {
+        fn keys(&self) -> Vec<Key> {
+            ["left", "right"]
+                .iter()
+                .map(|side| Key {
+                    symbols: format!("{}{:04}", side, self.0).into_bytes(),
+                    score: self.0 as i32,
+                    kind: KeyKind::Primary,
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn snapshots_ignore_later_writes() {
+        let tree = ConcurrentCompletionTree::new();
+        tree.write(|tree| tree.put(Pair(1)));
+        let before = tree.snapshot();
+        tree.write(|tree| {
+            tree.put(Pair(2));
+            tree.remove(&Pair(1));
+        });
+        let after = tree.snapshot();
+        assert_eq!(before.search(b"").collect::<Vec<_>>(), [&Pair(1); 2]);
+        assert_eq!(after.search(b"").collect::<Vec<_>>(), [&Pair(2); 2]);
+    }
+
+    #[test]
+    fn writes_share_unchanged_items() {
+        let tree = ConcurrentCompletionTree::new();
+        tree.write(|tree| tree.extend((0..1_000).map(Pair)));
+        let before = tree.snapshot();
+        tree.write(|tree| tree.put(Pair(1_000)));
+        let after = tree.snapshot();
+        let id = before.items.id(&Pair(0)).unwrap();
+        assert!(std::ptr::eq(before.items.get(id), after.items.get(id)));
+    }
+
+    #[test]
+    fn readers_never_see_half_a_write() {
+        let tree = ConcurrentCompletionTree::new();
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..200 {
+                    tree.write(|tree| {
+                        tree.put(Pair(i));
+                        tree.put(Pair(i + 1_000));
+                    });
+                }
+            });
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut seen = 0;
+                    while seen < 800 {
+                        let snapshot = tree.snapshot();
+                        let left = snapshot.search(b"left").count();
+                        let right = snapshot.search(b"right").count();
+                        // Both keys of both items land in the same write.
+                        assert_eq!(left, right);
+                        assert_eq!(left % 2, 0);
+                        assert!(left * 2 >= seen);
+                        seen = left * 2;
+                    }
+                });
+            }
+        });
+    }
+}
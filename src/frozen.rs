@@ -0,0 +1,317 @@
+use crate::{common_prefix_len, Completable, Completion, Scored, Symbol, Trail};
+use std::{
+    collections::{BinaryHeap, HashSet, VecDeque},
+    marker::PhantomData,
+    ops::Range,
+};
+
+/// Collects items up front so that the whole tree can be laid out in one go.
+pub struct CompletionTreeBuilder<T, S = i32, A = u8> {
+    items: Vec<T>,
+    _key: PhantomData<fn() -> (S, A)>,
+}
+impl<T, S, A> Default for CompletionTreeBuilder<T, S, A> {
+    fn default() -> Self {
+        Self {
+            items: Vec::new(),
+            _key: PhantomData,
+        }
+    }
+}
+impl<T, S, A> Extend<T> for CompletionTreeBuilder<T, S, A> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.items.extend(iter);
+    }
+}
+impl<T, S, A> CompletionTreeBuilder<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, item: T) {
+        self.items.push(item);
+    }
+
+    pub fn build(self) -> FrozenCompletionTree<T, S, A> {
+        let mut keys: Vec<(Vec<A>, S, u32)> = Vec::new();
+        for (index, item) in self.items.iter().enumerate() {
+            for key in item.keys() {
+                keys.push((key.symbols, key.score, index as u32));
+            }
+        }
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let mut tree = FrozenCompletionTree {
+            items: self.items,
+            nodes: Vec::new(),
+            labels: Vec::new(),
+            entries: Vec::new(),
+        };
+        if keys.is_empty() {
+            return tree;
+        }
+        // Lay the nodes out breadth-first, so that each node's children are allocated together.
+        // Every node covers a run of sorted keys that agree on their first `depth` symbols.
+        tree.nodes.push(FrozenNode::default());
+        let mut pending = VecDeque::new();
+        pending.push_back((0, 0..keys.len(), 0));
+        while let Some((index, range, depth)) = pending.pop_front() {
+            let keys = &keys[range.clone()];
+            let exact = keys.iter().take_while(|key| key.0.len() == depth).count();
+            let entries = tree.entries.len() as u32;
+            tree.entries
+                .extend(keys[..exact].iter().map(|&(_, score, item)| (item, score)));
+            tree.nodes[index].entries = entries..tree.entries.len() as u32;
+
+            let children = tree.nodes.len() as u32;
+            let mut start = exact;
+            while start < keys.len() {
+                let a = keys[start].0[depth];
+                let len = keys[start..]
+                    .iter()
+                    .take_while(|key| key.0[depth] == a)
+                    .count();
+                let group = &keys[start..start + len];
+                let common = common_prefix_len(&group[0].0, &group[len - 1].0);
+                let labels = tree.labels.len() as u32;
+                tree.labels.extend_from_slice(&group[0].0[depth..common]);
+                tree.nodes.push(FrozenNode {
+                    label: labels..tree.labels.len() as u32,
+                    ..FrozenNode::default()
+                });
+                let child_range = range.start + start..range.start + start + len;
+                pending.push_back((tree.nodes.len() - 1, child_range, common));
+                start += len;
+            }
+            tree.nodes[index].children = children..tree.nodes.len() as u32;
+        }
+        // Children always come after their parents, so a backwards pass sees them first.
+        for index in (0..tree.nodes.len()).rev() {
+            let node = &tree.nodes[index];
+            let entries = tree.entries[range(&node.entries)].iter().map(|e| e.1);
+            let children = tree.nodes[range(&node.children)]
+                .iter()
+                .map(|child| child.max_score.expect("children are scored first"));
+            tree.nodes[index].max_score = entries.chain(children).max();
+        }
+        tree
+    }
+}
+
+fn range(r: &Range<u32>) -> Range<usize> {
+    r.start as usize..r.end as usize
+}
+
+/// An immutable tree stored in a handful of flat arrays. Each item is stored once, and a node's
+/// label, items and children are each a contiguous run of the corresponding array.
+pub struct FrozenCompletionTree<T, S = i32, A = u8> {
+    items: Vec<T>,
+    nodes: Vec<FrozenNode<S>>,
+    labels: Vec<A>,
+    entries: Vec<(u32, S)>,
+}
+struct FrozenNode<S> {
+    label: Range<u32>,
+    entries: Range<u32>,
+    children: Range<u32>,
+    // Only `None` while the tree is being built.
+    max_score: Option<S>,
+}
+impl<S> Default for FrozenNode<S> {
+    fn default() -> Self {
+        Self {
+            label: 0..0,
+            entries: 0..0,
+            children: 0..0,
+            max_score: None,
+        }
+    }
+}
+
+impl<T, S, A> FrozenCompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    pub fn search(&self, prefix: &[A]) -> impl Iterator<Item = &T> {
+        let iter = match self.descendent(prefix) {
+            None => FrozenIter::empty(self),
+            Some((node, _)) => FrozenIter::from(self, node, ()),
+        };
+        iter.map(|(item, _, ())| item)
+    }
+
+    pub fn search_scored(&self, prefix: &[A]) -> impl Iterator<Item = Completion<'_, T, S, A>> {
+        let iter = match self.descendent(prefix) {
+            None => FrozenIter::empty(self),
+            Some((node, rest)) => FrozenIter::from(self, node, [prefix, rest].concat()),
+        };
+        iter.map(|(item, score, key)| Completion { item, score, key })
+    }
+
+    pub fn search_unique(&self, prefix: &[A]) -> impl Iterator<Item = &T> {
+        let mut seen = HashSet::new();
+        self.search(prefix).filter(move |item| seen.insert(*item))
+    }
+
+    pub fn top_k(&self, prefix: &[A], k: usize) -> Vec<&T> {
+        self.search(prefix).take(k).collect()
+    }
+
+    fn label(&self, node: u32) -> &[A] {
+        &self.labels[range(&self.nodes[node as usize].label)]
+    }
+
+    fn children(&self, node: u32) -> Range<u32> {
+        self.nodes[node as usize].children.clone()
+    }
+
+    fn max_score(&self, node: u32) -> S {
+        self.nodes[node as usize]
+            .max_score
+            .expect("scored during build")
+    }
+
+    // Mirrors `Node::descendent`, with children found by binary search on their first symbol.
+    fn descendent(&self, mut path: &[A]) -> Option<(u32, &[A])> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut cur = 0;
+        while let Some(a) = path.first() {
+            let children = self.children(cur);
+            let found = self.nodes[range(&children)]
+                .binary_search_by(|child| self.labels[child.label.start as usize].cmp(a))
+                .ok()?;
+            let child = children.start + found as u32;
+            let label = self.label(child);
+            if path.len() < label.len() {
+                return Some((child, label.strip_prefix(path)?));
+            }
+            path = path.strip_prefix(label)?;
+            cur = child;
+        }
+        Some((cur, &[]))
+    }
+}
+
+enum FrozenMarker<P> {
+    Item(u32, P),
+    Node(u32, P),
+}
+struct FrozenIter<'a, T, S, A, P> {
+    tree: &'a FrozenCompletionTree<T, S, A>,
+    queue: BinaryHeap<Scored<FrozenMarker<P>, S>>,
+}
+impl<'a, T, S, A, P> FrozenIter<'a, T, S, A, P>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    fn empty(tree: &'a FrozenCompletionTree<T, S, A>) -> Self {
+        Self {
+            tree,
+            queue: BinaryHeap::new(),
+        }
+    }
+    fn from(tree: &'a FrozenCompletionTree<T, S, A>, node: u32, trail: P) -> Self {
+        let mut iter = Self::empty(tree);
+        iter.queue.push(Scored {
+            item: FrozenMarker::Node(node, trail),
+            score: tree.max_score(node),
+        });
+        iter
+    }
+}
+impl<'a, T, S, A, P> Iterator for FrozenIter<'a, T, S, A, P>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+    P: Trail<A>,
+{
+    type Item = (&'a T, S, P);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.tree;
+        while let Some(cur) = self.queue.pop() {
+            match cur.item {
+                FrozenMarker::Item(item, trail) => {
+                    return Some((&tree.items[item as usize], cur.score, trail))
+                }
+                FrozenMarker::Node(node, trail) => {
+                    let entries = &tree.entries[range(&tree.nodes[node as usize].entries)];
+                    for &(item, score) in entries {
+                        self.queue.push(Scored {
+                            item: FrozenMarker::Item(item, trail.clone()),
+                            score,
+                        });
+                    }
+                    for child in tree.children(node) {
+                        self.queue.push(Scored {
+                            item: FrozenMarker::Node(child, trail.child(tree.label(child))),
+                            score: tree.max_score(child),
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{named, Named, PREFIXES};
+    use crate::{CompletionTree, CompletionTreeBuilder};
+
+    #[test]
+    fn frozen_search_matches_completion_tree() {
+        let mut builder = CompletionTreeBuilder::new();
+        builder.extend(named());
+        let frozen = builder.build();
+        let mut tree = CompletionTree::default();
+        for name in named() {
+            tree.put(name);
+        }
+        for prefix in PREFIXES {
+            assert_eq!(
+                frozen.search_scored(prefix).collect::<Vec<_>>(),
+                tree.search_scored(prefix).collect::<Vec<_>>()
+            );
+            assert_eq!(
+                frozen.search_unique(prefix).collect::<Vec<_>>(),
+                tree.search_unique(prefix).collect::<Vec<_>>()
+            );
+            for k in [0, 1, 3, 20] {
+                assert_eq!(frozen.top_k(prefix, k), tree.top_k(prefix, k));
+            }
+        }
+    }
+
+    #[test]
+    fn frozen_nodes_are_compressed() {
+        let mut builder = CompletionTreeBuilder::new();
+        for name in &named()[..5] {
+            builder.push(name.clone());
+        }
+        let frozen = builder.build();
+        // root, r, om, an, e, us, ulus, ube, ns, r
+        assert_eq!(frozen.nodes.len(), 10);
+        assert_eq!(frozen.items.len(), 5);
+    }
+
+    #[test]
+    fn empty_builders_build_empty_trees() {
+        let frozen = CompletionTreeBuilder::<Named>::new().build();
+        assert_eq!(frozen.search(b"").count(), 0);
+        assert_eq!(frozen.search(b"a").count(), 0);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::chunks::Chunks;
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hash},
+};
+
+// Every item in a tree, each stored once under a `u32` id that the nodes refer to it by. Ids of
+// removed items are handed out again. Finding an item's id goes through `buckets`, which groups
+// ids by the hash of the item they name, so that items aren't stored a second time as map keys.
+// Everything is kept in `Chunks`, so a clone shares it all and each change copies only a little.
+#[derive(Clone)]
+pub(crate) struct Items<T> {
+    slots: Chunks<Option<T>>,
+    vacant: Chunks<u32>,
+    // At least as many as there are items, and a power of two unless there are none.
+    buckets: Chunks<Vec<u32>>,
+    hasher: RandomState,
+}
+impl<T> Default for Items<T> {
+    fn default() -> Self {
+        Self {
+            slots: Chunks::default(),
+            vacant: Chunks::default(),
+            buckets: Chunks::default(),
+            hasher: RandomState::new(),
+        }
+    }
+}
+impl<T> Items<T> {
+    pub(crate) fn len(&self) -> usize {
+        self.slots.len() - self.vacant.len()
+    }
+
+    pub(crate) fn get(&self, id: u32) -> &T {
+        self.slots
+            .get(id as usize)
+            .and_then(Option::as_ref)
+            .expect("ids in use are occupied")
+    }
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn contains(&self, id: u32) -> bool {
+        self.slots.get(id as usize).is_some_and(Option::is_some)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (u32, &T)> {
+        let slots = self.slots.iter().enumerate();
+        slots.filter_map(|(id, slot)| Some((id as u32, slot.as_ref()?)))
+    }
+}
+impl<T: Clone + Eq + Hash> Items<T> {
+    // Gives each item the id of its position, including the empty positions.
+    pub(crate) fn from_slots(slots: Vec<Option<T>>) -> Self {
+        let mut items = Self::default();
+        for (id, slot) in slots.iter().enumerate() {
+            if slot.is_none() {
+                items.vacant.push(id as u32);
+            }
+        }
+        items.slots = slots.into_iter().collect();
+        items.rehash();
+        items
+    }
+
+    pub(crate) fn into_vec(self) -> Vec<T> {
+        self.slots.iter().flatten().cloned().collect()
+    }
+
+    pub(crate) fn id(&self, item: &T) -> Option<u32> {
+        let bucket = self.buckets.get(self.bucket(item))?;
+        bucket.iter().copied().find(|&id| self.get(id) == item)
+    }
+
+    // Stores `item`, taking the place and the id of any equal item already stored.
+    pub(crate) fn insert(&mut self, item: T) -> u32 {
+        if let Some(id) = self.id(&item) {
+            *self.slot(id) = Some(item);
+            return id;
+        }
+        let bucket = self.bucket(&item);
+        let id = match self.vacant.pop() {
+            Some(id) => {
+                *self.slot(id) = Some(item);
+                id
+            }
+            None => {
+                self.slots.push(Some(item));
+                (self.slots.len() - 1) as u32
+            }
+        };
+        if self.len() > self.buckets.len() {
+            self.rehash();
+        } else {
+            self.buckets.get_mut(bucket).expect("in range").push(id);
+        }
+        id
+    }
+
+    pub(crate) fn remove(&mut self, id: u32) -> T {
+        let item = self.slot(id).take().expect("ids in use are occupied");
+        let bucket = self.bucket(&item);
+        let bucket = self.buckets.get_mut(bucket).expect("in range");
+        bucket.retain(|&other| other != id);
+        self.vacant.push(id);
+        item
+    }
+
+    fn slot(&mut self, id: u32) -> &mut Option<T> {
+        self.slots.get_mut(id as usize).expect("ids are in range")
+    }
+
+    fn bucket(&self, item: &T) -> usize {
+        self.hasher.hash_one(item) as usize & self.buckets.len().wrapping_sub(1)
+    }
+
+    // Spreads the ids over twice as many buckets as there are items. This touches every item, but
+    // as the number of buckets doubles each time, it averages out to a constant cost per insert.
+    fn rehash(&mut self) {
+        let mask = (2 * self.len()).next_power_of_two() - 1;
+        let mut buckets = vec![Vec::new(); mask + 1];
+        for (id, slot) in self.slots.iter().enumerate() {
+            if let Some(item) = slot {
+                buckets[self.hasher.hash_one(item) as usize & mask].push(id as u32);
+            }
+        }
+        self.buckets = buckets.into_iter().collect();
+    }
+}
+
+// Only the slots are written out, since ids are their positions and the rest is derived from them.
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize> serde::Serialize for Items<T> {
+    fn serialize<Z: serde::Serializer>(&self, serializer: Z) -> Result<Z::Ok, Z::Error> {
+        serializer.collect_seq(self.slots.iter())
+    }
+}
+#[cfg(feature = "serde")]
+impl<'de, T: serde::Deserialize<'de> + Clone + Eq + Hash> serde::Deserialize<'de> for Items<T> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Self::from_slots(Vec::deserialize(deserializer)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Items;
+
+    #[test]
+    fn equal_items_share_an_id() {
+        let mut items = Items::default();
+        let a = items.insert("a");
+        let b = items.insert("b");
+        assert_ne!(a, b);
+        assert_eq!(items.insert("a"), a);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items.id(&"b"), Some(b));
+        assert_eq!(items.id(&"c"), None);
+    }
+
+    #[test]
+    fn removed_ids_are_reused() {
+        let mut items = Items::default();
+        let a = items.insert("a");
+        items.insert("b");
+        assert_eq!(items.remove(a), "a");
+        assert_eq!(items.id(&"a"), None);
+        assert_eq!(items.insert("c"), a);
+        assert_eq!(items.iter().collect::<Vec<_>>(), [(0, &"c"), (1, &"b")]);
+    }
+
+    #[test]
+    fn slots_keep_their_ids() {
+        let items = Items::from_slots(vec![Some("a"), None, Some("c")]);
+        assert_eq!(items.len(), 2);
+        assert_eq!(items.id(&"c"), Some(2));
+        assert_eq!(items.get(0), &"a");
+    }
+}
@@ -0,0 +1,340 @@
+//! Generators for the keys most `Completable` impls want, so that an item can be found by a word,
+//! an identifier fragment, a path segment or its initials rather than only by its start:
+//!
+//! ```ignore
+//! Keys::new(item.popularity)
+//!     .exact(&item.name)
+//!     .word_suffixes(&item.name, |score| score - 10)
+//!     .build()
+//! ```
+//!
+//! Every generator but `exact` and `alias` takes an adjustment from the item's base score to the
+//! score of the keys it produces. Keys are also marked with their `KeyKind`: a suffix that covers
+//! the whole text is primary and the rest are word suffixes, while initials are an alias, which
+//! leaves tree-wide adjustments to a `ScoringPolicy`. Fragments are kept as written, so matching
+//! regardless of case is left to a normalizer such as `CaseFold`.
+use crate::{Key, KeyKind, Symbol};
+
+/// Alphabets that text can be spelled in.
+pub trait FromText: Symbol {
+    fn from_text(text: &str) -> Vec<Self>;
+}
+impl FromText for u8 {
+    fn from_text(text: &str) -> Vec<Self> {
+        text.as_bytes().to_vec()
+    }
+}
+impl FromText for char {
+    fn from_text(text: &str) -> Vec<Self> {
+        text.chars().collect()
+    }
+}
+
+pub struct Keys<S = i32, A = u8> {
+    base: S,
+    keys: Vec<Key<S, A>>,
+}
+impl<S, A> Keys<S, A>
+where
+    S: Ord + Copy,
+    A: FromText,
+{
+    pub fn new(base: S) -> Self {
+        Self {
+            base,
+            keys: Vec::new(),
+        }
+    }
+
+    pub fn exact(self, text: &str) -> Self {
+        self.suffixes(text, &[0], |score| score)
+    }
+
+    pub fn alias(mut self, text: &str) -> Self {
+        if !text.is_empty() {
+            self.keys.push(Key {
+                symbols: A::from_text(text),
+                score: self.base,
+                kind: KeyKind::Alias,
+            });
+        }
+        self
+    }
+
+    /// "mary ann evans" gives "mary ann evans", "ann evans" and "evans".
+    pub fn word_suffixes(self, text: &str, adjust: impl Fn(S) -> S) -> Self {
+        let starts = starts(text, |prev, c, _| {
+            !c.is_whitespace() && prev.map_or(true, char::is_whitespace)
+        });
+        self.suffixes(text, &starts, adjust)
+    }
+
+    /// "parseHTTPRequest" gives "parseHTTPRequest", "HTTPRequest" and "Request", and
+    /// "parse_http_request" gives "parse_http_request", "http_request" and "request".
+    pub fn identifier_suffixes(self, text: &str, adjust: impl Fn(S) -> S) -> Self {
+        let starts = starts(text, identifier_start);
+        self.suffixes(text, &starts, adjust)
+    }
+
+    /// "src/bin/main.rs" gives "src/bin/main.rs", "bin/main.rs" and "main.rs".
+    pub fn path_suffixes(self, text: &str, adjust: impl Fn(S) -> S) -> Self {
+        let is_separator = |c: char| c == '/' || c == '\\';
+        let starts = starts(text, |prev, c, _| {
+            !is_separator(c) && prev.map_or(true, is_separator)
+        });
+        self.suffixes(text, &starts, adjust)
+    }
+
+    /// The first letter of every word, so "git hub" gives "gh" and "GitHub" gives "GH".
+    pub fn initials(mut self, text: &str, adjust: impl Fn(S) -> S) -> Self {
+        let initials: String = starts(text, identifier_start)
+            .into_iter()
+            .filter_map(|start| text[start..].chars().next())
+            .collect();
+        if !initials.is_empty() {
+            self.keys.push(Key {
+                symbols: A::from_text(&initials),
+                score: adjust(self.base),
+                kind: KeyKind::Alias,
+            });
+        }
+        self
+    }
+
+    /// Where generators agree on a key, only the best-scoring one is kept, preferring the most
+    /// primary kind on a tie.
+    pub fn build(mut self) -> Vec<Key<S, A>> {
+        self.keys.sort_by(|a, b| {
+            let by_symbols = a.symbols.cmp(&b.symbols);
+            by_symbols
+                .then(b.score.cmp(&a.score))
+                .then(a.kind.cmp(&b.kind))
+        });
+        self.keys.dedup_by(|a, b| a.symbols == b.symbols);
+        self.keys
+    }
+
+    fn suffixes(mut self, text: &str, starts: &[usize], adjust: impl Fn(S) -> S) -> Self {
+        let score = adjust(self.base);
+        for (i, &start) in starts.iter().enumerate() {
+            if start < text.len() {
+                self.keys.push(Key {
+                    symbols: A::from_text(&text[start..]),
+                    score,
+                    kind: match i {
+                        0 => KeyKind::Primary,
+                        _ => KeyKind::WordSuffix,
+                    },
+                });
+            }
+        }
+        self
+    }
+}
+
+// The byte offsets of every character that `is_start` picks out, given the characters around it.
+fn starts(text: &str, is_start: impl Fn(Option<char>, char, Option<char>) -> bool) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut prev = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((at, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, next)| next);
+        if is_start(prev, c, next) {
+            starts.push(at);
+        }
+        prev = Some(c);
+    }
+    starts
+}
+
+fn identifier_start(prev: Option<char>, c: char, next: Option<char>) -> bool {
+    let prev = match prev {
+        _ if !c.is_alphanumeric() => return false,
+        None => return true,
+        Some(prev) => prev,
+    };
+    !prev.is_alphanumeric()
+        || (!prev.is_uppercase() && c.is_uppercase())
+        // The last capital of an acronym starts the next word, as in "HTTPRequest".
+        || (prev.is_uppercase() && c.is_uppercase() && next.is_some_and(char::is_lowercase))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keys;
+    use crate::{CaseFold, Completable, CompletionTree, Key, KeyKind};
+
+    fn keys(keys: Keys) -> Vec<(String, i32)> {
+        let keys = keys.build().into_iter();
+        keys.map(|key| (String::from_utf8(key.symbols).unwrap(), key.score))
+            .collect()
+    }
+
+    #[test]
+    fn word_suffixes() {
+        assert_eq!(
+            keys(Keys::new(5).word_suffixes("  mary ann\tevans", |s| s - 1)),
+            [
+                ("ann\tevans".to_owned(), 4),
+                ("evans".to_owned(), 4),
+                ("mary ann\tevans".to_owned(), 4)
+            ]
+        );
+        assert!(keys(Keys::new(5).word_suffixes("   ", |s| s)).is_empty());
+    }
+
+    #[test]
+    fn identifier_suffixes() {
+        let suffixes = |text| {
+            let keys = keys(Keys::new(0).identifier_suffixes(text, |s| s));
+            keys.into_iter().map(|key| key.0).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            suffixes("parseHTTPRequest"),
+            ["HTTPRequest", "Request", "parseHTTPRequest"]
+        );
+        assert_eq!(
+            suffixes("parse_http-request"),
+            ["http-request", "parse_http-request", "request"]
+        );
+        assert_eq!(suffixes("__init__"), ["init__"]);
+        assert_eq!(suffixes("v2Beta"), ["Beta", "v2Beta"]);
+    }
+
+    #[test]
+    fn path_suffixes() {
+        assert_eq!(
+            keys(Keys::new(0).path_suffixes("/src\\bin/main.rs", |s| s + 1)),
+            [
+                ("bin/main.rs".to_owned(), 1),
+                ("main.rs".to_owned(), 1),
+                ("src\\bin/main.rs".to_owned(), 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn initials() {
+        assert_eq!(
+            keys(Keys::new(3).initials("git hub", |s| s)),
+            [("gh".to_owned(), 3)]
+        );
+        assert_eq!(
+            keys(Keys::new(3).initials("GitHub", |s| s)),
+            [("GH".to_owned(), 3)]
+        );
+        assert_eq!(
+            keys(Keys::new(3).initials("read_HTTPResponse", |s| s)),
+            [("rHR".to_owned(), 3)]
+        );
+        assert!(keys(Keys::new(3).initials("--", |s| s)).is_empty());
+    }
+
+    #[test]
+    fn agreeing_generators_keep_the_best_score() {
+        assert_eq!(
+            keys(
+                Keys::new(10)
+                    .exact("ann evans")
+                    .word_suffixes("ann evans", |s| s - 5)
+            ),
+            [("ann evans".to_owned(), 10), ("evans".to_owned(), 5)]
+        );
+    }
+
+    #[test]
+    fn keys_are_marked_with_their_kind() {
+        let kinds = Keys::<i32>::new(0)
+            .exact("git hub")
+            .word_suffixes("git hub", |s| s)
+            .initials("git hub", |s| s)
+            .alias("github")
+            .build();
+        let kinds = kinds.iter().map(|key| (&key.symbols[..], key.kind));
+        assert_eq!(
+            kinds.collect::<Vec<_>>(),
+            [
+                (&b"gh"[..], KeyKind::Alias),
+                (b"git hub", KeyKind::Primary),
+                (b"github", KeyKind::Alias),
+                (b"hub", KeyKind::WordSuffix)
+            ]
+        );
+    }
+
+    #[cfg(feature = "derive")]
+    #[test]
+    fn derived_keys() {
+        #[derive(Clone, PartialEq, Eq, Hash, crate::Completable)]
+        struct Document {
+            #[complete(key, tokenize = "words")]
+            #[complete(key, tokenize = "initials")]
+            title: String,
+            #[complete(key, tokenize = "path")]
+            path: &'static str,
+            #[complete(alias)]
+            slug: &'static str,
+            #[complete(score)]
+            views: u32,
+        }
+        #[derive(Clone, PartialEq, Eq, Hash, crate::Completable)]
+        struct Tag(#[complete(key)] &'static str);
+
+        let document = Document {
+            title: "Release Notes".to_owned(),
+            path: "docs/release.md",
+            slug: "relnotes",
+            views: 7,
+        };
+        let expected = Keys::new(7)
+            .word_suffixes("Release Notes", |s| s)
+            .initials("Release Notes", |s| s)
+            .path_suffixes("docs/release.md", |s| s)
+            .alias("relnotes")
+            .build();
+        let symbols = |keys: Vec<Key<u32>>| -> Vec<(Vec<u8>, u32, crate::KeyKind)> {
+            keys.into_iter()
+                .map(|key| (key.symbols, key.score, key.kind))
+                .collect()
+        };
+        assert_eq!(symbols(document.keys()), symbols(expected));
+        let tag = Tag("urgent").keys();
+        assert_eq!(
+            (&tag[0].symbols[..], tag[0].score, tag.len()),
+            (&b"urgent"[..], 0, 1)
+        );
+
+        #[derive(Clone, PartialEq, Eq, Hash, crate::Completable)]
+        #[complete(alphabet = "char")]
+        struct Place(#[complete(key, tokenize = "words")] &'static str);
+        let mut tree = CompletionTree::<Place, i32, char>::default();
+        tree.put(Place("Zürich Hauptbahnhof"));
+        assert_eq!(tree.search_str("Zü").count(), 1);
+        assert_eq!(tree.search_str("Haupt").count(), 1);
+    }
+
+    #[derive(Clone, PartialEq, Eq, Hash)]
+    struct Command(&'static str, i32);
+    impl Completable for Command {
+        fn keys(&self) -> Vec<Key> {
+            Keys::new(self.1)
+                .exact(self.0)
+                .identifier_suffixes(self.0, |score| score - 10)
+                .initials(self.0, |score| score - 5)
+                .build()
+        }
+    }
+
+    #[test]
+    fn generated_keys_in_a_tree() {
+        let mut tree = CompletionTree::default();
+        tree.set_normalizer(CaseFold);
+        tree.put(Command("git_hub_sync", 3));
+        tree.put(Command("getHostName", 2));
+        tree.put(Command("hubris", 1));
+        let names = |query: &[u8]| tree.search(query).map(|c| c.0).collect::<Vec<_>>();
+        assert_eq!(names(b"gh"), ["git_hub_sync", "getHostName"]);
+        assert_eq!(names(b"hub"), ["hubris", "git_hub_sync"]);
+        assert_eq!(names(b"name"), ["getHostName"]);
+    }
+}
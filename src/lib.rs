@@ -1,142 +1,734 @@
+mod binary;
+mod chunks;
+mod concurrent;
+mod frozen;
+mod items;
+pub mod keys;
+mod mapped;
+mod normalize;
+mod owned;
+#[cfg(feature = "rayon")]
+mod parallel;
+mod persistent;
+mod policy;
+mod score;
+mod symbol;
+
+pub use binary::{Codec, FormatError};
+#[cfg(feature = "derive")]
+pub use completion_trie_derive::Completable;
+pub use concurrent::ConcurrentCompletionTree;
+pub use frozen::{CompletionTreeBuilder, FrozenCompletionTree};
+pub use mapped::{MappedCompletion, MappedCompletionTree};
+#[cfg(feature = "unicode")]
+pub use normalize::UnicodeFold;
+pub use normalize::{CaseFold, Normalizer};
+pub use owned::OwnedCompletionIter;
+pub use persistent::PersistentCompletionTree;
+pub use policy::{Penalties, ScoringPolicy};
+pub use score::{SaturatingAdd, SaturatingSub, TotalOrd};
+pub use symbol::Symbol;
+
+use items::Items;
+
+// Lets code generated by the derive macro name this crate the same way inside it as outside.
+#[cfg(feature = "derive")]
+extern crate self as completion_trie;
+
 use std::{
-    collections::{BTreeMap, BinaryHeap},
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
     hash::Hash,
+    iter::FromIterator,
+    sync::Arc,
 };
 
-type Score = i32;
-struct Scored<T> {
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Scored<T, S> {
     pub item: T,
-    pub score: Score,
+    pub score: S,
 }
-impl<T> Ord for Scored<T> {
+impl<T, S: Ord> Ord for Scored<T, S> {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.score.cmp(&other.score)
     }
 }
-impl<T> PartialOrd for Scored<T> {
+impl<T, S: Ord> PartialOrd for Scored<T, S> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         Some(self.cmp(other))
     }
 }
-impl<T> PartialEq for Scored<T> {
+impl<T, S: Ord> PartialEq for Scored<T, S> {
     fn eq(&self, other: &Self) -> bool {
         self.score == other.score
     }
 }
-impl<T> Eq for Scored<T> {}
-pub struct Key {
-    pub bytes: Vec<u8>,
-    pub score: Score,
+impl<T, S: Ord> Eq for Scored<T, S> {}
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Key<S = i32, A = u8> {
+    pub symbols: Vec<A>,
+    pub score: S,
+    pub kind: KeyKind,
 }
-pub trait Completable: Eq + Clone + Hash {
-    fn keys(&self) -> Vec<Key>;
+/// What a key is to its item, so that a tree's `ScoringPolicy` can rank some kinds of match below
+/// others.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyKind {
+    /// The item's own name.
+    #[default]
+    Primary,
+    /// Another name for the item.
+    Alias,
+    /// A key starting partway through one of the item's names, such as a later word.
+    WordSuffix,
+}
+pub trait Completable<S = i32, A = u8>: Eq + Clone + Hash {
+    fn keys(&self) -> Vec<Key<S, A>>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion<'a, T, S = i32, A = u8> {
+    pub item: &'a T,
+    pub score: S,
+    /// The full key that matched, from the root of the tree rather than from the search prefix.
+    pub key: Vec<A>,
 }
 
-pub struct CompletionTree<T>(Option<Node<T>>);
-impl<T> Default for CompletionTree<T> {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[derive(Clone)]
+pub struct CompletionTree<T, S = i32, A = u8> {
+    // Nodes refer to items by their id in `items`, so each item is stored once however many keys
+    // it has.
+    root: Option<Arc<Node<u32, S, A>>>,
+    items: Items<T>,
+    // Applied to every key as it is stored and to every query, while items are kept as they are.
+    // It isn't saved along with the tree, so a loaded tree needs it set again.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    normalizer: Option<Arc<dyn Normalizer<A>>>,
+    // Likewise applied to every key as it is stored, and likewise not saved.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    policy: Option<Arc<dyn ScoringPolicy<S>>>,
+}
+impl<T, S, A> Default for CompletionTree<T, S, A> {
     fn default() -> Self {
-        Self(None)
+        Self {
+            root: None,
+            items: Items::default(),
+            normalizer: None,
+            policy: None,
+        }
     }
 }
-impl<T> CompletionTree<T>
+// What a tree deserializes from, before its node item ids are checked against its items.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(bound(deserialize = "T: serde::Deserialize<'de> + Clone + Eq + Hash, \
+                             S: serde::Deserialize<'de>, \
+                             A: serde::Deserialize<'de> + Ord"))]
+struct SerializedTree<T, S, A> {
+    root: Option<Arc<Node<u32, S, A>>>,
+    items: Items<T>,
+}
+#[cfg(feature = "serde")]
+impl<'de, T, S, A> serde::Deserialize<'de> for CompletionTree<T, S, A>
 where
-    T: Completable,
+    T: serde::Deserialize<'de> + Clone + Eq + Hash,
+    S: serde::Deserialize<'de>,
+    A: serde::Deserialize<'de> + Ord,
 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        fn check<T, S, A>(node: &Node<u32, S, A>, items: &Items<T>) -> bool {
+            node.items.iter().all(|item| items.contains(item.item))
+                && node.children.values().all(|child| check(child, items))
+        }
+        let tree = SerializedTree::deserialize(deserializer)?;
+        if let Some(root) = tree.root.as_ref() {
+            if !check(root, &tree.items) {
+                return Err(serde::de::Error::custom("item index out of range"));
+            }
+        }
+        Ok(Self {
+            root: tree.root,
+            items: tree.items,
+            normalizer: None,
+            policy: None,
+        })
+    }
+}
+impl<T, S, A> FromIterator<T> for CompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut tree = Self::default();
+        tree.extend(iter);
+        tree
+    }
+}
+impl<T, S, A> Extend<T> for CompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    // Sorts every key up front so that keys sharing a prefix descend from the root together,
+    // rather than each walking the whole path alone.
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let mut keys = self.insert_all(iter.into_iter().map(|item| {
+            let keys = item.keys();
+            (item, keys)
+        }));
+        keys.sort_by(|a, b| a.0.cmp(&b.0));
+        if let Some(max_score) = keys.iter().map(|key| key.1).max() {
+            let root = self
+                .root
+                .get_or_insert_with(|| Arc::new(Node::new(Vec::new(), max_score)));
+            Arc::make_mut(root).put_sorted(&keys, 0);
+        }
+    }
+}
+impl<T, S, A> CompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    /// Keys are normalized as they are stored, so any items already in the tree are stored again.
+    pub fn set_normalizer(&mut self, normalizer: impl Normalizer<A> + 'static) {
+        self.normalizer = Some(Arc::new(normalizer));
+        self.rekey();
+    }
+
+    /// As with normalization, any items already in the tree are stored again.
+    pub fn set_scoring_policy(&mut self, policy: impl ScoringPolicy<S> + 'static) {
+        self.policy = Some(Arc::new(policy));
+        self.rekey();
+    }
+
+    fn rekey(&mut self) {
+        self.root = None;
+        let items = std::mem::take(&mut self.items);
+        self.extend(items.into_vec());
+    }
+
+    fn normalize<'q>(&self, symbols: &'q [A]) -> Cow<'q, [A]> {
+        match self.normalizer.as_ref() {
+            None => Cow::Borrowed(symbols),
+            Some(normalizer) => Cow::Owned(normalizer.normalize(symbols)),
+        }
+    }
+
+    // A key as the tree stores it, after normalization and the scoring policy.
+    fn prepare_key(&self, key: Key<S, A>) -> Key<S, A> {
+        let symbols = match self.normalizer.as_ref() {
+            None => key.symbols,
+            Some(normalizer) => normalizer.normalize(&key.symbols),
+        };
+        let score = match self.policy.as_ref() {
+            None => key.score,
+            Some(policy) => policy.score(key.kind, key.score),
+        };
+        Key {
+            symbols,
+            score,
+            kind: key.kind,
+        }
+    }
+
+    // Stores each item and returns the keys it should be placed under, unsorted. Like `put`, an
+    // item replaces any equal one, keys and all, whether that one was stored before the batch or
+    // earlier in it.
+    fn insert_all(
+        &mut self,
+        items: impl IntoIterator<Item = (T, Vec<Key<S, A>>)>,
+    ) -> Vec<(Vec<A>, S, u32)> {
+        let mut latest = HashMap::new();
+        let mut keys = Vec::new();
+        for (batch, (item, item_keys)) in items.into_iter().enumerate() {
+            if self
+                .items
+                .id(&item)
+                .is_some_and(|id| !latest.contains_key(&id))
+            {
+                self.remove(&item);
+            }
+            let id = self.items.insert(item);
+            latest.insert(id, batch);
+            for key in item_keys {
+                let key = self.prepare_key(key);
+                keys.push((batch, (key.symbols, key.score, id)));
+            }
+        }
+        keys.into_iter()
+            .filter(|(batch, key)| latest[&key.2] == *batch)
+            .map(|(_, key)| key)
+            .collect()
+    }
+
+    /// An equal item already in the tree is replaced, along with every key it was stored under.
     pub fn put(&mut self, item: T) {
-        for key in item.keys() {
-            self.0
-                .get_or_insert_with(|| Node::new(key.score))
-                .put_key(key, item.clone());
+        self.remove(&item);
+        let keys = item.keys();
+        let id = self.items.insert(item);
+        for key in keys {
+            let key = self.prepare_key(key);
+            let root = self
+                .root
+                .get_or_insert_with(|| Arc::new(Node::new(Vec::new(), key.score)));
+            Arc::make_mut(root).put_key(key, id);
         }
     }
 
-    pub fn search(&self, prefix: &[u8]) -> impl Iterator<Item = &T> {
-        match self.descendent(prefix) {
+    /// The number of distinct items, however many keys each of them has.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.len() == 0
+    }
+
+    /// The same as `put`, which already replaces any equal item.
+    pub fn upsert(&mut self, item: T) {
+        self.put(item);
+    }
+
+    pub fn remove(&mut self, item: &T) {
+        let id = match self.items.id(item) {
+            None => return,
+            Some(id) => id,
+        };
+        let stored = self.items.remove(id);
+        let keys = stored.keys().into_iter().map(|key| self.prepare_key(key));
+        let keys: Vec<_> = keys.collect();
+        if let Some(root) = self.root.as_mut() {
+            let root = Arc::make_mut(root);
+            for key in keys {
+                root.remove_key(&key.symbols, &id);
+            }
+            if root.is_empty() {
+                self.root = None;
+            }
+        }
+    }
+
+    pub fn search(&self, prefix: &[A]) -> impl Iterator<Item = &T> {
+        let prefix = self.normalize(prefix);
+        let iter = match self.root.as_ref().and_then(|root| root.descendent(&prefix)) {
             None => CompletionIter::empty(),
-            Some(node) => CompletionIter::from(node),
+            Some((node, _)) => CompletionIter::from(node, ()),
+        };
+        iter.map(move |(&id, _, ())| self.items.get(id))
+    }
+
+    /// The matched keys are reported as they are stored, which is after normalization.
+    pub fn search_scored(&self, prefix: &[A]) -> impl Iterator<Item = Completion<'_, T, S, A>> {
+        let prefix = self.normalize(prefix);
+        let iter = match self.root.as_ref().and_then(|root| root.descendent(&prefix)) {
+            None => CompletionIter::empty(),
+            Some((node, rest)) => CompletionIter::from(node, [&prefix, rest].concat()),
+        };
+        iter.map(move |(&id, score, key)| Completion {
+            item: self.items.get(id),
+            score,
+            key,
+        })
+    }
+
+    pub fn top_k(&self, prefix: &[A], k: usize) -> Vec<&T> {
+        let mut results = Vec::with_capacity(k);
+        let prefix = self.normalize(prefix);
+        let node = match self.root.as_ref().and_then(|root| root.descendent(&prefix)) {
+            Some((node, _)) if k > 0 => node,
+            _ => return results,
+        };
+        // Every queued entry stands for a distinct item scoring at least as well as the entry:
+        // itself, or for a node the item that attains its `max_score`. `floor` holds the best `k`
+        // of those scores, so once it is full anything that can't beat its minimum is dropped
+        // rather than queued.
+        let mut floor = BinaryHeap::with_capacity(k + 1);
+        let mut admit = |score: S| {
+            if floor.len() == k && floor.peek().is_some_and(|&Reverse(min)| score <= min) {
+                return false;
+            }
+            floor.push(Reverse(score));
+            if floor.len() > k {
+                floor.pop();
+            }
+            true
+        };
+        admit(node.max_score);
+        let mut queue = BinaryHeap::new();
+        queue.push(Scored {
+            item: ExploreMarker::Node(node, ()),
+            score: node.max_score,
+        });
+        while let Some(cur) = queue.pop() {
+            match cur.item {
+                ExploreMarker::Item(&id, ()) => {
+                    results.push(self.items.get(id));
+                    if results.len() == k {
+                        break;
+                    }
+                }
+                ExploreMarker::Node(node, ()) => {
+                    // The entry attaining this node's `max_score` inherits its place in `floor`.
+                    let mut inherited = false;
+                    let mut inherits = |score: S| {
+                        let inherits = !inherited && score == node.max_score;
+                        inherited |= inherits;
+                        inherits
+                    };
+                    for item in &node.items {
+                        if inherits(item.score) || admit(item.score) {
+                            queue.push(Scored {
+                                item: ExploreMarker::Item(&item.item, ()),
+                                score: item.score,
+                            });
+                        }
+                    }
+                    for child in node.children.values() {
+                        if inherits(child.max_score) || admit(child.max_score) {
+                            queue.push(Scored {
+                                item: ExploreMarker::Node(child, ()),
+                                score: child.max_score,
+                            });
+                        }
+                    }
+                }
+            }
         }
+        results
+    }
+
+    pub fn search_unique(&self, prefix: &[A]) -> impl Iterator<Item = &T> {
+        // Results arrive best-first, so the first sighting of an item is at its best score.
+        let prefix = self.normalize(prefix);
+        let iter = match self.root.as_ref().and_then(|root| root.descendent(&prefix)) {
+            None => CompletionIter::empty(),
+            Some((node, _)) => CompletionIter::from(node, ()),
+        };
+        let mut seen = HashSet::new();
+        iter.filter(move |&(&id, _, ())| seen.insert(id))
+            .map(move |(&id, _, ())| self.items.get(id))
     }
 
-    fn descendent(&self, prefix: &[u8]) -> Option<&Node<T>> {
-        self.0.as_ref()?.descendent(prefix)
+    pub fn search_tokens(&self, query: &[A]) -> impl Iterator<Item = &T> {
+        let mut tokens = query
+            .split(Symbol::is_separator)
+            .filter(|token| !token.is_empty());
+        let first = tokens.next().unwrap_or_default();
+        let rest: Vec<Vec<A>> = tokens
+            .map(|token| self.normalize(token).into_owned())
+            .collect();
+        // Candidates for the first token arrive best-first; every other token only filters them.
+        self.search_unique(first).filter(move |item| {
+            let keys: Vec<_> = item
+                .keys()
+                .into_iter()
+                .map(|key| self.prepare_key(key))
+                .collect();
+            rest.iter()
+                .all(|token| keys.iter().any(|key| key.symbols.starts_with(token)))
+        })
+    }
+}
+impl<T, S> CompletionTree<T, S, char>
+where
+    T: Completable<S, char>,
+    S: Ord + Copy,
+{
+    pub fn search_str(&self, prefix: &str) -> impl Iterator<Item = &T> {
+        self.search(&prefix.chars().collect::<Vec<_>>())
+    }
+}
+impl<T, S, A> CompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy + SaturatingSub,
+    A: Symbol,
+{
+    pub fn fuzzy_search(
+        &self,
+        query: &[A],
+        max_edits: usize,
+        edit_penalty: S,
+    ) -> impl Iterator<Item = &T> {
+        let query = self.normalize(query);
+        let iter = match self.root.as_ref() {
+            None => FuzzyIter::empty(&query, max_edits, edit_penalty),
+            Some(node) => FuzzyIter::from(node, &query, max_edits, edit_penalty),
+        };
+        iter.map(move |&id| self.items.get(id))
+    }
+}
+impl<T, S, A> CompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy + SaturatingAdd,
+    A: Symbol,
+{
+    /// Ranks by scores that depend on the query rather than only on the item: `filter` drops
+    /// items and `boost` rescores them, given their stored score.
+    ///
+    /// Searches stay best-first by leaning on every node's `max_score`, the best stored score
+    /// anywhere beneath it. An item is only yielded once no node still waiting to be explored
+    /// could hold anything that outranks it, and for that the bound has to cover boosted scores
+    /// too. So boosts are capped: an item never scores more than `max_boost` above its stored
+    /// score, however much `boost` asks for, and each node is ranked at its `max_score` plus
+    /// `max_boost`. Boosts may be negative. A cap well above what `boost` ever gives only means
+    /// exploring more nodes before the first result. The cap is added with `SaturatingAdd`, so it
+    /// can't overflow.
+    pub fn search_with(
+        &self,
+        prefix: &[A],
+        mut filter: impl FnMut(&T) -> bool,
+        max_boost: S,
+        mut boost: impl FnMut(&T, S) -> S,
+    ) -> impl Iterator<Item = &T> {
+        let prefix = self.normalize(prefix);
+        let mut iter = BoostedIter {
+            queue: BinaryHeap::new(),
+            max_boost,
+            filter: move |&id: &u32| filter(self.items.get(id)),
+            boost: move |&id: &u32, score| boost(self.items.get(id), score),
+        };
+        if let Some((node, _)) = self.root.as_ref().and_then(|root| root.descendent(&prefix)) {
+            iter.push_node(node);
+        }
+        iter.map(move |&id| self.items.get(id))
     }
 }
 
-struct Node<T> {
-    items: Vec<Scored<T>>,
-    children: BTreeMap<u8, Node<T>>,
-    max_score: Score,
+// Chains of single-child nodes are compressed into one node, so every node but the root carries
+// the label of the edge leading into it. `children` is keyed by the first symbol of each label.
+// Children are shared rather than owned, so that trees can share structure: every change goes
+// through `Arc::make_mut`, which copies a node only if some other tree still refers to it.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(bound(deserialize = "T: serde::Deserialize<'de>, \
+                               S: serde::Deserialize<'de>, \
+                               A: serde::Deserialize<'de> + Ord"))
+)]
+#[derive(Clone)]
+struct Node<T, S, A> {
+    label: Vec<A>,
+    items: Vec<Scored<T, S>>,
+    children: BTreeMap<A, Arc<Node<T, S, A>>>,
+    max_score: S,
 }
-impl<T> Node<T>
+impl<T, S, A> Node<T, S, A>
 where
-    T: Completable,
+    T: Clone + Eq,
+    S: Ord + Copy,
+    A: Symbol,
 {
-    fn new(max_score: Score) -> Self {
+    fn new(label: Vec<A>, max_score: S) -> Self {
         Self {
+            label,
             items: Default::default(),
             children: Default::default(),
             max_score,
         }
     }
-    fn put_key(&mut self, key: Key, item: T) {
+    fn put_key(&mut self, key: Key<S, A>, item: T) {
         let score = key.score;
         let mut cur = self;
-        for b in key.bytes {
+        let mut rest = &key.symbols[..];
+        while let Some(&a) = rest.first() {
             cur.max_score = std::cmp::max(score, cur.max_score);
-            cur = cur.children.entry(b).or_insert_with(|| Node::new(score));
+            let child = cur
+                .children
+                .entry(a)
+                .or_insert_with(|| Arc::new(Node::new(rest.to_vec(), score)));
+            let child = Arc::make_mut(child);
+            let common = common_prefix_len(&child.label, rest);
+            if common < child.label.len() {
+                child.split(common);
+            }
+            rest = &rest[common..];
+            cur = child;
         }
         cur.max_score = std::cmp::max(score, cur.max_score);
         cur.items.push(Scored { item, score });
     }
 
-    fn descendent(&self, path: &[u8]) -> Option<&Self> {
+    // `keys` must be sorted, and must all start with the path to this node, which is `depth`
+    // symbols long.
+    fn put_sorted(&mut self, keys: &[(Vec<A>, S, T)], depth: usize) {
+        let exact = keys.iter().take_while(|key| key.0.len() == depth).count();
+        let (exact, mut rest) = keys.split_at(exact);
+        for (_, score, item) in exact {
+            self.max_score = std::cmp::max(*score, self.max_score);
+            self.items.push(Scored {
+                item: item.clone(),
+                score: *score,
+            });
+        }
+        while let Some(a) = rest.first().map(|key| key.0[depth]) {
+            let len = rest.iter().take_while(|key| key.0[depth] == a).count();
+            let (group, tail) = rest.split_at(len);
+            rest = tail;
+            let max_score = group
+                .iter()
+                .map(|key| key.1)
+                .max()
+                .expect("non-empty group");
+            let shared = common_prefix_len(&group[0].0, &group[len - 1].0);
+            self.max_score = std::cmp::max(max_score, self.max_score);
+            let child = self.children.entry(a).or_insert_with(|| {
+                Arc::new(Node::new(group[0].0[depth..shared].to_vec(), max_score))
+            });
+            let child = Arc::make_mut(child);
+            let common = common_prefix_len(&child.label, &group[0].0[depth..shared]);
+            if common < child.label.len() {
+                child.split(common);
+            }
+            child.put_sorted(group, depth + common);
+        }
+    }
+
+    // Cuts this node's label at `at`, pushing everything it holds down into a new child.
+    fn split(&mut self, at: usize) {
+        let suffix = self.label.split_off(at);
+        let mut lower = Node::new(suffix, self.max_score);
+        std::mem::swap(&mut lower.items, &mut self.items);
+        std::mem::swap(&mut lower.children, &mut self.children);
+        self.children.insert(lower.label[0], Arc::new(lower));
+    }
+
+    // The inverse of `split`, for a node left with nothing of its own but a single child.
+    fn merge_single_child(&mut self) {
+        if !self.items.is_empty() || self.children.len() != 1 {
+            return;
+        }
+        let (_, child) = self.children.pop_first().expect("exactly one child");
+        let child = Arc::unwrap_or_clone(child);
+        self.label.extend(child.label);
+        self.items = child.items;
+        self.children = child.children;
+        self.max_score = child.max_score;
+    }
+
+    fn remove_key(&mut self, path: &[A], item: &T) {
+        match path.first() {
+            None => self.items.retain(|scored| &scored.item != item),
+            Some(a) => {
+                if let Some(child) = self.children.get_mut(a) {
+                    if let Some(rest) = path.strip_prefix(&child.label[..]) {
+                        let child = Arc::make_mut(child);
+                        child.remove_key(rest, item);
+                        if child.is_empty() {
+                            self.children.remove(a);
+                        } else {
+                            child.merge_single_child();
+                        }
+                    }
+                }
+            }
+        }
+        self.recompute_max_score();
+    }
+
+    fn recompute_max_score(&mut self) {
+        let items = self.items.iter().map(|scored| scored.score);
+        let children = self.children.values().map(|child| child.max_score);
+        if let Some(max_score) = items.chain(children).max() {
+            self.max_score = max_score;
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty() && self.children.is_empty()
+    }
+
+    // Finds the shallowest node whose path starts with `path`. The search may end partway along
+    // that node's label, in which case the unmatched remainder of the label is returned with it.
+    fn descendent(self: &Arc<Self>, mut path: &[A]) -> Option<(&Arc<Self>, &[A])> {
         let mut cur = self;
-        for b in path {
-            cur = cur.children.get(b)?;
+        while let Some(a) = path.first() {
+            let child = cur.children.get(a)?;
+            if path.len() < child.label.len() {
+                let rest = child.label.strip_prefix(path)?;
+                return Some((child, rest));
+            }
+            path = path.strip_prefix(&child.label[..])?;
+            cur = child;
         }
-        Some(cur)
+        Some((cur, &[]))
     }
 }
 
-enum ExploreMarker<'a, T> {
-    Item(&'a T),
-    Node(&'a Node<T>),
+fn common_prefix_len<A: PartialEq>(a: &[A], b: &[A]) -> usize {
+    a.iter().zip(b).take_while(|(a, b)| a == b).count()
 }
-struct CompletionIter<'a, T> {
-    queue: BinaryHeap<Scored<ExploreMarker<'a, T>>>,
+
+// Whatever a traversal remembers about the path from the root to the node it is exploring.
+trait Trail<A>: Clone {
+    fn child(&self, label: &[A]) -> Self;
 }
-impl<'a, T> CompletionIter<'a, T> {
+impl<A> Trail<A> for () {
+    fn child(&self, _: &[A]) -> Self {}
+}
+impl<A: Clone> Trail<A> for Vec<A> {
+    fn child(&self, label: &[A]) -> Self {
+        [self, label].concat()
+    }
+}
+
+enum ExploreMarker<'a, T, S, A, P> {
+    Item(&'a T, P),
+    Node(&'a Node<T, S, A>, P),
+}
+struct CompletionIter<'a, T, S, A, P> {
+    queue: BinaryHeap<Scored<ExploreMarker<'a, T, S, A, P>, S>>,
+}
+impl<'a, T, S, A, P> CompletionIter<'a, T, S, A, P>
+where
+    S: Ord + Copy,
+{
     fn empty() -> Self {
         Self {
             queue: BinaryHeap::new(),
         }
     }
-    fn from(node: &'a Node<T>) -> Self {
+    fn from(node: &'a Node<T, S, A>, trail: P) -> Self {
         let mut queue = BinaryHeap::new();
         queue.push(Scored {
-            item: ExploreMarker::Node(node),
+            item: ExploreMarker::Node(node, trail),
             score: node.max_score,
         });
         Self { queue }
     }
 }
-impl<'a, T> Iterator for CompletionIter<'a, T> {
-    type Item = &'a T;
+impl<'a, T, S, A, P> Iterator for CompletionIter<'a, T, S, A, P>
+where
+    S: Ord + Copy,
+    A: Symbol,
+    P: Trail<A>,
+{
+    type Item = (&'a T, S, P);
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(cur) = self.queue.pop() {
             match cur.item {
-                ExploreMarker::Item(item) => return Some(item),
-                ExploreMarker::Node(node) => {
+                ExploreMarker::Item(item, trail) => return Some((item, cur.score, trail)),
+                ExploreMarker::Node(node, trail) => {
                     for item in &node.items {
                         self.queue.push(Scored {
-                            item: ExploreMarker::Item(&item.item),
+                            item: ExploreMarker::Item(&item.item, trail.clone()),
                             score: item.score,
                         });
                     }
                     for child in node.children.values() {
                         self.queue.push(Scored {
-                            item: ExploreMarker::Node(child),
+                            item: ExploreMarker::Node(child, trail.child(&child.label)),
                             score: child.max_score,
                         });
                     }
@@ -147,27 +739,363 @@ impl<'a, T> Iterator for CompletionIter<'a, T> {
     }
 }
 
+// Explores like `CompletionIter`, except that items are queued at their boosted scores. A node is
+// queued at its `max_score` plus `max_boost`, which bounds the boosted score of everything below
+// it, so nothing popped after an item can outscore it.
+struct BoostedIter<'a, T, S, A, F, B> {
+    queue: BinaryHeap<Scored<ExploreMarker<'a, T, S, A, ()>, S>>,
+    max_boost: S,
+    filter: F,
+    boost: B,
+}
+impl<'a, T, S, A, F, B> BoostedIter<'a, T, S, A, F, B>
+where
+    S: Ord + Copy + SaturatingAdd,
+{
+    fn push_node(&mut self, node: &'a Node<T, S, A>) {
+        self.queue.push(Scored {
+            item: ExploreMarker::Node(node, ()),
+            score: node.max_score.saturating_add(self.max_boost),
+        });
+    }
+}
+impl<'a, T, S, A, F, B> Iterator for BoostedIter<'a, T, S, A, F, B>
+where
+    S: Ord + Copy + SaturatingAdd,
+    F: FnMut(&T) -> bool,
+    B: FnMut(&T, S) -> S,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(cur) = self.queue.pop() {
+            match cur.item {
+                ExploreMarker::Item(item, ()) => return Some(item),
+                ExploreMarker::Node(node, ()) => {
+                    for item in &node.items {
+                        if !(self.filter)(&item.item) {
+                            continue;
+                        }
+                        let ceiling = item.score.saturating_add(self.max_boost);
+                        let score = (self.boost)(&item.item, item.score);
+                        self.queue.push(Scored {
+                            item: ExploreMarker::Item(&item.item, ()),
+                            score: std::cmp::min(score, ceiling),
+                        });
+                    }
+                    for child in node.children.values() {
+                        self.push_node(child);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// Walks the trie alongside a Levenshtein DP row, so that `row[i]` is the edit distance between
+// `query[..i]` and the path to the current node. A node matches once some prefix of its path is
+// within `max_edits` of the full query; `best` tracks the cheapest such prefix seen so far.
+enum FuzzyMarker<'a, T, S, A> {
+    Item(&'a T),
+    Node {
+        node: &'a Node<T, S, A>,
+        row: Vec<usize>,
+        best: usize,
+    },
+}
+struct FuzzyIter<'a, T, S, A> {
+    queue: BinaryHeap<Scored<FuzzyMarker<'a, T, S, A>, S>>,
+    query: Vec<A>,
+    max_edits: usize,
+    edit_penalty: S,
+}
+impl<'a, T, S, A> FuzzyIter<'a, T, S, A>
+where
+    S: Ord + Copy + SaturatingSub,
+    A: Symbol,
+{
+    fn empty(query: &[A], max_edits: usize, edit_penalty: S) -> Self {
+        Self {
+            queue: BinaryHeap::new(),
+            query: query.to_vec(),
+            max_edits,
+            edit_penalty,
+        }
+    }
+    fn from(node: &'a Node<T, S, A>, query: &[A], max_edits: usize, edit_penalty: S) -> Self {
+        let mut iter = Self::empty(query, max_edits, edit_penalty);
+        let row: Vec<usize> = (0..=query.len()).collect();
+        let best = query.len();
+        iter.push_node(node, row, best);
+        iter
+    }
+    fn penalize(&self, score: S, edits: usize) -> S {
+        // Only subtraction is required of the score type, so apply the penalty once per edit.
+        (0..edits).fold(score, |score, _| score.saturating_sub(self.edit_penalty))
+    }
+    fn push_node(&mut self, node: &'a Node<T, S, A>, row: Vec<usize>, best: usize) {
+        // No descendent can do better than the cheapest cell in the current row.
+        let lower_bound = std::cmp::min(best, row.iter().copied().min().unwrap_or(best));
+        if lower_bound > self.max_edits {
+            return;
+        }
+        self.queue.push(Scored {
+            score: self.penalize(node.max_score, lower_bound),
+            item: FuzzyMarker::Node { node, row, best },
+        });
+    }
+    fn next_row(&self, row: &[usize], a: A) -> Vec<usize> {
+        let mut next = Vec::with_capacity(row.len());
+        next.push(row[0] + 1);
+        for (i, &q) in self.query.iter().enumerate() {
+            let substitution = row[i] + (q != a) as usize;
+            let deletion = row[i + 1] + 1;
+            let insertion = next[i] + 1;
+            next.push(substitution.min(deletion).min(insertion));
+        }
+        next
+    }
+}
+impl<'a, T, S, A> Iterator for FuzzyIter<'a, T, S, A>
+where
+    S: Ord + Copy + SaturatingSub,
+    A: Symbol,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(cur) = self.queue.pop() {
+            match cur.item {
+                FuzzyMarker::Item(item) => return Some(item),
+                FuzzyMarker::Node { node, row, best } => {
+                    if best <= self.max_edits {
+                        for item in &node.items {
+                            self.queue.push(Scored {
+                                item: FuzzyMarker::Item(&item.item),
+                                score: self.penalize(item.score, best),
+                            });
+                        }
+                    }
+                    for child in node.children.values() {
+                        // Every point along the label is a prefix that might match the query.
+                        let (mut row, mut best) = (row.clone(), best);
+                        for &a in &child.label {
+                            row = self.next_row(&row, a);
+                            best = std::cmp::min(best, row[self.query.len()]);
+                        }
+                        self.push_node(child, row, best);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// Items, names and queries shared by the tests of every module.
+#[cfg(test)]
+mod fixtures {
+    use crate::{binary, Codec, Completable, CompletionTree, FormatError, Key, KeyKind};
+
+    pub(crate) fn word_suffixes<S: Copy>(mut s: &str, score: S) -> Vec<Key<S>> {
+        let mut buf = Vec::new();
+        loop {
+            buf.push(Key {
+                symbols: s.as_bytes().to_vec(),
+                score,
+                kind: if buf.is_empty() {
+                    KeyKind::Primary
+                } else {
+                    KeyKind::WordSuffix
+                },
+            });
+            match s.find(' ') {
+                Some(idx) => s = &s[idx + 1..],
+                None => return buf,
+            }
+        }
+    }
+
+    // Found by any word it contains.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub(crate) struct Named(pub String, pub i32);
+    impl Named {
+        pub(crate) fn new(name: &str, score: i32) -> Self {
+            Named(name.to_owned(), score)
+        }
+    }
+    impl Completable for Named {
+        fn keys(&self) -> Vec<Key> {
+            word_suffixes(&self.0, self.1)
+        }
+    }
+    impl Codec for Named {
+        fn encode(&self, out: &mut Vec<u8>) {
+            (self.0.clone(), self.1).encode(out);
+        }
+        fn decode(input: &mut &[u8]) -> Result<Self, FormatError> {
+            let (name, score) = Codec::decode(input)?;
+            Ok(Named(name, score))
+        }
+    }
+
+    // Found only by its start.
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    pub(crate) struct Word(pub &'static str, pub i32);
+    impl Completable for Word {
+        fn keys(&self) -> Vec<Key> {
+            vec![Key {
+                symbols: self.0.as_bytes().to_vec(),
+                score: self.1,
+                kind: KeyKind::Primary,
+            }]
+        }
+    }
+
+    // Names that share prefixes at several depths, and some with more than one word.
+    pub(crate) const NAMES: [(&str, i32); 8] = [
+        ("romane", 1),
+        ("romanus", 2),
+        ("romulus", 3),
+        ("rubens", 4),
+        ("ruber", 5),
+        ("rubicon river", 0),
+        ("rom", 6),
+        ("river rom", -1),
+    ];
+    pub(crate) const PREFIXES: [&[u8]; 9] = [
+        b"", b"r", b"ro", b"rom", b"roma", b"rube", b"riv", b"x", b"romx",
+    ];
+
+    // A node to be written by `raw_file`.
+    pub(crate) struct RawNode {
+        label: &'static [u8],
+        items: Vec<(u32, i32)>,
+        children: Vec<usize>,
+    }
+    pub(crate) fn node(
+        label: &'static [u8],
+        items: Vec<(u32, i32)>,
+        children: Vec<usize>,
+    ) -> RawNode {
+        RawNode {
+            label,
+            items,
+            children,
+        }
+    }
+
+    // A file in the binary format laid out exactly as given, with a valid checksum, so that only
+    // structural checks stand between it and a tree. Children are indices into `nodes`, which are
+    // written in order with the last as the root; an index past the end stands for an offset where
+    // no node starts.
+    pub(crate) fn raw_file(items: &[Named], nodes: &[RawNode]) -> Vec<u8> {
+        let mut body = Vec::new();
+        binary::encode_items(items.iter(), &mut body);
+        let nodes_offset = body.len() as u64;
+        let mut offsets = Vec::new();
+        let mut at = body.len();
+        for node in nodes {
+            offsets.push(at as u64);
+            at += 16 + node.label.len() + 8 * (node.items.len() + node.children.len());
+        }
+        for node in nodes {
+            3i32.encode(&mut body);
+            node.label.to_vec().encode(&mut body);
+            node.items.encode(&mut body);
+            let children = node.children.iter();
+            let children = children.map(|&i| offsets.get(i).copied().unwrap_or(1));
+            children.collect::<Vec<_>>().encode(&mut body);
+        }
+        let root_offset = offsets.last().copied().unwrap_or(binary::NO_ROOT);
+        binary::seal(body, nodes_offset, root_offset)
+    }
+
+    pub(crate) fn named() -> Vec<Named> {
+        NAMES
+            .iter()
+            .map(|&(name, score)| Named::new(name, score))
+            .collect()
+    }
+
+    pub(crate) fn make_tree() -> CompletionTree<Named> {
+        named().into_iter().collect()
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{Completable, CompletionTree, Key};
+    use crate::fixtures::{word_suffixes, Named, NAMES, PREFIXES};
+    use crate::{CaseFold, Completable, CompletionTree, Key, KeyKind, Node, Penalties, TotalOrd};
     use itertools::Itertools;
+    use std::hash::{Hash, Hasher};
 
     impl Completable for (&str, i32) {
         fn keys(&self) -> Vec<Key> {
-            let mut buf = Vec::new();
-            let mut s = self.0;
-            loop {
-                buf.push(Key {
-                    bytes: s.as_bytes().to_vec(),
-                    score: self.1,
-                });
-                match s.find(' ') {
-                    Some(idx) => s = &s[idx + 1..],
-                    None => return buf,
-                }
-            }
+            word_suffixes(self.0, self.1)
+        }
+    }
+    impl Completable<TotalOrd<f32>> for (&str, TotalOrd<f32>) {
+        fn keys(&self) -> Vec<Key<TotalOrd<f32>>> {
+            word_suffixes(self.0, self.1)
+        }
+    }
+    impl Completable<u32> for (&str, u32) {
+        fn keys(&self) -> Vec<Key<u32>> {
+            word_suffixes(self.0, self.1)
+        }
+    }
+    impl Completable<(u8, i32)> for (&str, (u8, i32)) {
+        fn keys(&self) -> Vec<Key<(u8, i32)>> {
+            word_suffixes(self.0, self.1)
+        }
+    }
+    // Identified by name alone, so that a new popularity updates the same item.
+    #[derive(Clone, Debug)]
+    struct Contact(&'static str, i32);
+    impl PartialEq for Contact {
+        fn eq(&self, other: &Self) -> bool {
+            self.0 == other.0
+        }
+    }
+    impl Eq for Contact {}
+    impl Hash for Contact {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            self.0.hash(state);
         }
     }
+    impl Completable for Contact {
+        fn keys(&self) -> Vec<Key> {
+            (self.0, self.1).keys()
+        }
+    }
+
+    #[derive(Clone, PartialEq, Eq, Hash)]
+    struct Chars(&'static str, i32);
+    impl Completable<i32, char> for Chars {
+        fn keys(&self) -> Vec<Key<i32, char>> {
+            vec![Key {
+                symbols: self.0.chars().collect(),
+                score: self.1,
+                kind: KeyKind::Primary,
+            }]
+        }
+    }
+
+    #[derive(Clone, PartialEq, Eq, Hash)]
+    struct Tokens(&'static [u32], i32);
+    impl Completable<i32, u32> for Tokens {
+        fn keys(&self) -> Vec<Key<i32, u32>> {
+            vec![Key {
+                symbols: self.0.to_vec(),
+                score: self.1,
+                kind: KeyKind::Primary,
+            }]
+        }
+    }
+
     macro_rules! make_tree {
         ($($key:expr => $score:expr, )*) => {{
             let mut tree = CompletionTree::default();
@@ -176,6 +1104,34 @@ mod tests {
         }};
     }
 
+    fn node_count<T, S, A>(tree: &CompletionTree<T, S, A>) -> usize {
+        fn count<T, S, A>(node: &Node<T, S, A>) -> usize {
+            1 + node
+                .children
+                .values()
+                .map(|child| count(child))
+                .sum::<usize>()
+        }
+        tree.root.as_ref().map_or(0, |root| count(root))
+    }
+
+    // Short names made of a few letters and spaces, so that keys share prefixes at every depth.
+    fn random_tree(mut seed: u32, n: usize) -> CompletionTree<Named> {
+        let mut next = move |n: u32| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) % n
+        };
+        let mut tree = CompletionTree::default();
+        for _ in 0..n {
+            let len = 1 + next(6) as usize;
+            let name: String = (0..len)
+                .map(|_| b"abc "[next(4) as usize] as char)
+                .collect();
+            tree.put(Named(name, next(20) as i32));
+        }
+        tree
+    }
+
     #[test]
     fn smoke_test() {
         let tree = make_tree!(
@@ -212,15 +1168,638 @@ mod tests {
         );
     }
 
+    #[test]
+    fn float_scores() {
+        let tree = make_tree!(
+            "alice" => TotalOrd(0.25),
+            "alex" => TotalOrd(0.5),
+            "adam" => TotalOrd(0.125),
+        );
+        assert_eq!(
+            tree.search(b"").map(|r| r.0).collect::<Vec<_>>(),
+            ["alex", "alice", "adam"]
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"alx", 1, TotalOrd(0.375))
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["alex", "alice"]
+        );
+    }
+
+    #[test]
+    fn tuple_scores_rank_by_tier_first() {
+        let tree = make_tree!(
+            "alice" => (1, 100),
+            "alex" => (2, 0),
+            "adam" => (1, 200),
+        );
+        assert_eq!(
+            tree.search(b"a").map(|r| r.0).collect::<Vec<_>>(),
+            ["alex", "adam", "alice"]
+        );
+    }
+
+    #[test]
+    fn char_keys_never_split_codepoints() {
+        let mut tree = CompletionTree::default();
+        tree.put(Chars("été", 2));
+        tree.put(Chars("étoile", 1));
+        tree.put(Chars("èze", 3));
+        assert_eq!(
+            tree.search_str("é").map(|r| r.0).collect::<Vec<_>>(),
+            ["été", "étoile"]
+        );
+        assert_eq!(
+            tree.search(&['è']).map(|r| r.0).collect::<Vec<_>>(),
+            ["èze"]
+        );
+        // "é" and "è" share their first UTF-8 byte, which a byte trie would have matched on.
+        assert_eq!(tree.search(&['\u{c3}']).count(), 0);
+    }
+
+    #[test]
+    fn token_id_keys() {
+        let mut tree = CompletionTree::default();
+        tree.put(Tokens(&[7, 1, 9], 1));
+        tree.put(Tokens(&[7, 2], 5));
+        tree.put(Tokens(&[8], 3));
+        assert_eq!(
+            tree.search(&[7]).map(|r| r.0).collect::<Vec<_>>(),
+            [&[7, 2][..], &[7, 1, 9]]
+        );
+        assert_eq!(tree.search_tokens(&[7, 1]).count(), 1);
+    }
+
+    #[test]
+    fn fuzzy_search_without_edits_is_prefix_search() {
+        let tree = make_tree!(
+            "alice" => 1,
+            "alex" => 4,
+            "adam" => -3,
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"al", 0, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["alex", "alice"]
+        );
+        assert_eq!(tree.fuzzy_search(b"z", 0, 1).count(), 0);
+    }
+
+    #[test]
+    fn fuzzy_search_tolerates_typos() {
+        let tree = make_tree!(
+            "alice" => 1,
+            "alex" => 4,
+            "adam" => -3,
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"alx", 1, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["alex", "alice"]
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"dam", 1, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["adam"]
+        );
+        assert_eq!(tree.fuzzy_search(b"dam", 0, 1).count(), 0);
+    }
+
+    #[test]
+    fn fuzzy_search_trades_edits_against_score() {
+        let tree = make_tree!(
+            "bob" => 5,
+            "rob" => 1,
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"ro", 1, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["bob", "rob"]
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"ro", 1, 10)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["rob", "bob"]
+        );
+    }
+
+    #[test]
+    fn fuzzy_search_penalties_saturate() {
+        let tree = make_tree!(
+            "alex" => 0u32,
+            "alice" => 3u32,
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"alx", 1, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["alice", "alex"]
+        );
+        let tree = make_tree!(
+            "alex" => i32::MIN,
+            "alice" => i32::MAX,
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"alx", 1, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["alice", "alex"]
+        );
+    }
+
+    #[test]
+    fn scored_search_reports_scores_and_matched_keys() {
+        let tree = make_tree!(
+            "hello world" => 1,
+            "goodbye world" => 0,
+        );
+        assert_eq!(
+            tree.search_scored(b"wor")
+                .map(|c| (c.item.0, c.score, c.key))
+                .collect::<Vec<_>>(),
+            [
+                ("hello world", 1, b"world".to_vec()),
+                ("goodbye world", 0, b"world".to_vec()),
+            ]
+        );
+        assert_eq!(
+            tree.search_scored(b"g")
+                .map(|c| (c.item.0, c.score, c.key))
+                .collect::<Vec<_>>(),
+            [("goodbye world", 0, b"goodbye world".to_vec())]
+        );
+        assert_eq!(tree.search_scored(b"x").count(), 0);
+    }
+
+    #[test]
+    fn single_child_chains_are_compressed() {
+        let mut tree = make_tree!(
+            "romane" => 1,
+            "romanus" => 2,
+            "romulus" => 3,
+            "rubens" => 4,
+            "ruber" => 5,
+        );
+        // root, r, om, an, e, us, ulus, ube, ns, r
+        assert_eq!(node_count(&tree), 10);
+        assert_eq!(
+            tree.search(b"rom").map(|r| r.0).collect::<Vec<_>>(),
+            ["romulus", "romanus", "romane"]
+        );
+        assert_eq!(
+            tree.search(b"ro").map(|r| r.0).collect::<Vec<_>>(),
+            ["romulus", "romanus", "romane"]
+        );
+        assert_eq!(tree.search(b"roma").count(), 2);
+        assert_eq!(tree.search(b"romx").count(), 0);
+        assert_eq!(
+            tree.search_scored(b"rube")
+                .map(|c| c.key)
+                .collect::<Vec<_>>(),
+            [b"ruber".to_vec(), b"rubens".to_vec()]
+        );
+
+        // With "romulus" gone, "om" and "an" merge back into a single edge.
+        tree.remove(&("romulus", 3));
+        assert_eq!(node_count(&tree), 8);
+        assert_eq!(
+            tree.search_scored(b"ro").map(|c| c.key).collect::<Vec<_>>(),
+            [b"romanus".to_vec(), b"romane".to_vec()]
+        );
+        assert_eq!(
+            tree.fuzzy_search(b"rmo", 1, 1)
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["romanus", "romane"]
+        );
+    }
+
+    #[test]
+    fn top_k_matches_the_search_order() {
+        let tree = make_tree!(
+            "alice" => 1,
+            "alex" => 4,
+            "adam" => -3,
+            "albert" => 2,
+            "bob" => 7,
+        );
+        for k in 0..6 {
+            for prefix in [&b""[..], b"a", b"al", b"z"] {
+                assert_eq!(
+                    tree.top_k(prefix, k),
+                    tree.search(prefix).take(k).collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn top_k_agrees_with_search_on_a_bushy_tree() {
+        let tree = random_tree(42, 500);
+        for k in [1, 5, 50, 1000] {
+            for prefix in [&b""[..], b"a", b"ab", b"c a"] {
+                // Ties may come out in either order, so only compare the scores.
+                assert_eq!(
+                    tree.top_k(prefix, k)
+                        .iter()
+                        .map(|r| r.1)
+                        .collect::<Vec<_>>(),
+                    tree.search(prefix).take(k).map(|r| r.1).collect::<Vec<_>>()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn search_with_filters_and_caps_boosts() {
+        let tree: CompletionTree<(&str, i32)> = vec![("alpha", 4), ("alpine", 3), ("altar", 1)]
+            .into_iter()
+            .collect();
+        let names = |max_boost| {
+            let results = tree.search_with(
+                b"al",
+                |r| r.0 != "alpha",
+                max_boost,
+                |r, score| {
+                    if r.0 == "altar" {
+                        score + 5
+                    } else {
+                        score
+                    }
+                },
+            );
+            results.map(|r| r.0).collect::<Vec<_>>()
+        };
+        assert_eq!(names(5), ["altar", "alpine"]);
+        // "altar" may only climb to 1 + 1, which doesn't pass "alpine".
+        assert_eq!(names(1), ["alpine", "altar"]);
+        let all = tree.search_with(b"", |_| true, 0, |_, score| score);
+        assert_eq!(
+            all.map(|r| r.1).collect::<Vec<_>>(),
+            tree.search(b"").map(|r| r.1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn search_with_saturates_at_the_largest_score() {
+        let tree = make_tree!(
+            "max" => i32::MAX,
+            "min" => i32::MIN,
+        );
+        let results = tree.search_with(b"m", |_| true, 1, |_, score| score);
+        assert_eq!(results.map(|r| r.0).collect::<Vec<_>>(), ["max", "min"]);
+        let results = tree.search_with(b"m", |_| true, i32::MAX, |_, _| i32::MAX);
+        assert_eq!(
+            results.map(|r| r.1).collect::<Vec<_>>(),
+            [i32::MAX, i32::MIN]
+        );
+    }
+
+    #[test]
+    fn search_with_float_scores() {
+        let tree = make_tree!(
+            "lemon" => TotalOrd(0.5),
+            "lime" => TotalOrd(0.75),
+            "lychee" => TotalOrd(0.25),
+        );
+        let results = tree.search_with(
+            b"l",
+            |r| r.0 != "lime",
+            TotalOrd(0.5),
+            |r, score| {
+                if r.0 == "lychee" {
+                    score + TotalOrd(0.5)
+                } else {
+                    score
+                }
+            },
+        );
+        assert_eq!(
+            results.map(|r| r.0).collect::<Vec<_>>(),
+            ["lychee", "lemon"]
+        );
+    }
+
+    #[test]
+    fn search_with_stays_best_first() {
+        let tree = random_tree(7, 300);
+        // Longer names are boosted further, past the cap of 4 once they are five long.
+        let boost = |r: &Named| r.1 + r.0.len() as i32 * 3 - 8;
+        let capped = |r: &Named| std::cmp::min(boost(r), r.1 + 4);
+        for prefix in [&b""[..], b"a", b"ab", b"c a"] {
+            let scores: Vec<i32> = tree
+                .search_with(prefix, |r| r.0.len() != 2, 4, |r, _| boost(r))
+                .map(capped)
+                .collect();
+            let mut expected: Vec<i32> = tree
+                .search(prefix)
+                .filter(|r| r.0.len() != 2)
+                .map(capped)
+                .collect();
+            expected.sort_by(|a, b| b.cmp(a));
+            assert_eq!(scores, expected);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip_preserves_search_results() {
+        let mut tree = CompletionTree::default();
+        for (name, score) in [("hello world", 1), ("goodbye world", 0), ("help", 3)] {
+            tree.put(Named::new(name, score));
+        }
+        tree.remove(&Named::new("help", 3));
+        let json = serde_json::to_string(&tree).unwrap();
+        let loaded: CompletionTree<Named> = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            loaded.root.as_ref().unwrap().max_score,
+            tree.root.as_ref().unwrap().max_score
+        );
+        for prefix in [&b""[..], b"h", b"wor", b"x"] {
+            assert_eq!(
+                loaded.search_scored(prefix).collect::<Vec<_>>(),
+                tree.search_scored(prefix).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_rejects_dangling_item_ids() {
+        let mut tree = CompletionTree::default();
+        tree.put(Named::new("hello", 1));
+        tree.put(Named::new("help", 3));
+        tree.remove(&Named::new("help", 3));
+        let json = serde_json::to_string(&tree).unwrap();
+        assert!(json.contains(r#""item":0"#));
+        // Past the end of the items, and at the slot left empty by the removal.
+        for id in [7, 1] {
+            let json = json.replace(r#""item":0"#, &format!(r#""item":{}"#, id));
+            let err = serde_json::from_str::<CompletionTree<Named>>(&json).err();
+            assert!(err.unwrap().to_string().contains("item index out of range"));
+        }
+    }
+
+    #[test]
+    fn bulk_construction_matches_repeated_puts() {
+        let names: Vec<_> = NAMES.iter().copied().chain([("", 7)]).collect();
+        let mut put = CompletionTree::default();
+        for &name in &names {
+            put.put(name);
+        }
+        let collected: CompletionTree<_> = names.iter().copied().collect();
+        // Extending a non-empty tree has to split and reuse the nodes already there.
+        let mut extended: CompletionTree<_> = names[..3].iter().copied().collect();
+        extended.extend(names[3..].iter().copied());
+        for tree in [&collected, &extended] {
+            assert_eq!(node_count(tree), node_count(&put));
+            for prefix in PREFIXES {
+                assert_eq!(
+                    tree.search_scored(prefix).collect::<Vec<_>>(),
+                    put.search_scored(prefix).collect::<Vec<_>>()
+                );
+            }
+        }
+        assert!(collected.items.iter().eq(put.items.iter()));
+        assert_eq!(collected.len(), 9);
+    }
+
     #[test]
     fn subsequences_are_not_matched() {
-        // Honestly this is kind of unfortunate, I wish it worked.
+        // Honestly this is kind of unfortunate, I wish it worked. See `search_tokens` instead.
         let tree = make_tree!(
             "jeffrey smith" => 1,
         );
         assert_eq!(tree.search(b"jeff smith").count(), 0);
     }
 
+    #[test]
+    fn removed_items_are_not_returned() {
+        let mut tree = make_tree!(
+            "alice" => 1,
+            "alex" => 4,
+            "adam" => -3,
+        );
+        tree.remove(&("alex", 4));
+        assert_eq!(
+            tree.search(b"").map(|r| r.0).collect::<Vec<_>>(),
+            ["alice", "adam"]
+        );
+        assert_eq!(tree.search(b"alex").count(), 0);
+    }
+
+    #[test]
+    fn removal_lowers_max_score() {
+        let mut tree = make_tree!(
+            "ab" => 1,
+            "ac" => 2,
+            "b" => 5,
+            "bc" => 3,
+        );
+        tree.remove(&("b", 5));
+        assert_eq!(tree.root.as_ref().unwrap().max_score, 3);
+        assert_eq!(
+            tree.search(b"").map(|r| r.0).collect::<Vec<_>>(),
+            ["bc", "ac", "ab"]
+        );
+    }
+
+    #[test]
+    fn removing_every_item_empties_the_tree() {
+        let mut tree = make_tree!(
+            "hello world" => 1,
+        );
+        tree.remove(&("hello world", 1));
+        assert!(tree.root.is_none());
+        assert_eq!(tree.search(b"").count(), 0);
+    }
+
+    #[test]
+    fn upsert_replaces_stale_scores() {
+        let mut tree = CompletionTree::default();
+        tree.put(Contact("alice", 1));
+        tree.put(Contact("alex", 4));
+        tree.upsert(Contact("alice", 7));
+        assert_eq!(
+            tree.search(b"a").map(|c| (c.0, c.1)).collect::<Vec<_>>(),
+            [("alice", 7), ("alex", 4)]
+        );
+        tree.upsert(Contact("alice", 0));
+        assert_eq!(
+            tree.search(b"a").map(|c| (c.0, c.1)).collect::<Vec<_>>(),
+            [("alex", 4), ("alice", 0)]
+        );
+        assert_eq!(tree.root.as_ref().unwrap().max_score, 4);
+    }
+
+    #[test]
+    fn upsert_inserts_new_items() {
+        let mut tree = CompletionTree::default();
+        tree.upsert(Contact("bob smith", 2));
+        assert_eq!(
+            tree.search(b"smith").map(|c| c.0).collect::<Vec<_>>(),
+            ["bob smith"]
+        );
+    }
+
+    #[test]
+    fn normalized_keys_and_queries() {
+        let mut tree = CompletionTree::default();
+        tree.put(Contact("Mary Ann Evans", 1));
+        // Items already in the tree are stored again under normalized keys.
+        tree.set_normalizer(CaseFold);
+        tree.put(Contact("George ELIOT", 2));
+        let names = |results: Vec<&Contact>| results.iter().map(|c| c.0).collect::<Vec<_>>();
+        assert_eq!(names(tree.search(b"mary").collect()), ["Mary Ann Evans"]);
+        assert_eq!(names(tree.search(b"ELIO").collect()), ["George ELIOT"]);
+        assert_eq!(names(tree.top_k(b"", 1)), ["George ELIOT"]);
+        assert_eq!(
+            names(tree.search_tokens(b"EVANS mary").collect()),
+            ["Mary Ann Evans"]
+        );
+        assert_eq!(
+            names(tree.fuzzy_search(b"GEROGE", 2, 1).collect()),
+            ["George ELIOT"]
+        );
+        assert_eq!(tree.search_scored(b"Ann").next().unwrap().key, b"ann evans");
+        tree.remove(&Contact("George ELIOT", 0));
+        assert_eq!(names(tree.search(b"").collect()), ["Mary Ann Evans"; 3]);
+    }
+
+    #[test]
+    fn scoring_policy_penalizes_word_suffixes() {
+        let mut tree = CompletionTree::default();
+        tree.put(Contact("ann evans", 1));
+        tree.put(Contact("mary ann", 3));
+        let names =
+            |tree: &CompletionTree<Contact>| tree.search(b"ann").map(|c| c.0).collect::<Vec<_>>();
+        assert_eq!(names(&tree), ["mary ann", "ann evans"]);
+        // Items already in the tree are stored again under the policy's scores.
+        tree.set_scoring_policy(Penalties {
+            alias: 1,
+            word_suffix: 5,
+        });
+        assert_eq!(names(&tree), ["ann evans", "mary ann"]);
+        let scores = tree.search_scored(b"").map(|s| s.score).collect::<Vec<_>>();
+        assert_eq!(scores, [3, 1, -2, -4]);
+        tree.set_scoring_policy(|kind, score| match kind {
+            KeyKind::Primary => score,
+            _ => score * 10,
+        });
+        assert_eq!(names(&tree), ["mary ann", "ann evans"]);
+    }
+
+    #[test]
+    fn penalties_saturate_unsigned_scores() {
+        let mut tree = CompletionTree::default();
+        tree.set_scoring_policy(Penalties {
+            alias: 1u32,
+            word_suffix: 5,
+        });
+        tree.put(("ann evans", 2u32));
+        let scores = tree.search_scored(b"").map(|s| (s.key, s.score));
+        assert_eq!(
+            scores.collect::<Vec<_>>(),
+            [(b"ann evans".to_vec(), 2), (b"evans".to_vec(), 0)]
+        );
+    }
+
+    #[test]
+    fn items_are_stored_once() {
+        let mut tree = CompletionTree::default();
+        tree.put(Contact("mary ann evans", 1));
+        tree.put(Contact("george eliot", 2));
+        tree.put(Contact("mary ann evans", 3));
+        assert_eq!(tree.len(), 2);
+        // The second put replaced the first, keys and all.
+        assert_eq!(tree.search(b"evans").map(|c| c.1).collect::<Vec<_>>(), [3]);
+        tree.remove(&Contact("mary ann evans", 0));
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree.search(b"").count(), 2);
+    }
+
+    #[test]
+    fn replaced_items_leave_no_keys_behind() {
+        // Identified by id alone, so that a record can be renamed.
+        #[derive(Clone, Debug)]
+        struct Record(u32, &'static str);
+        impl PartialEq for Record {
+            fn eq(&self, other: &Self) -> bool {
+                self.0 == other.0
+            }
+        }
+        impl Eq for Record {}
+        impl Hash for Record {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.hash(state);
+            }
+        }
+        impl Completable for Record {
+            fn keys(&self) -> Vec<Key> {
+                word_suffixes(self.1, 0)
+            }
+        }
+        let names = |tree: &CompletionTree<Record>, prefix: &[u8]| {
+            tree.search(prefix).map(|r| r.1).collect::<Vec<_>>()
+        };
+
+        let mut tree = CompletionTree::default();
+        tree.put(Record(1, "bob"));
+        tree.put(Record(1, "robert"));
+        assert_eq!(names(&tree, b"b"), Vec::<&str>::new());
+        assert_eq!(names(&tree, b"r"), ["robert"]);
+        tree.remove(&Record(1, ""));
+        // The freed id goes to an unrelated record, which the old keys mustn't find.
+        tree.put(Record(2, "zed"));
+        assert_eq!(names(&tree, b"b"), Vec::<&str>::new());
+        assert_eq!(names(&tree, b""), ["zed"]);
+
+        let tree: CompletionTree<_> = vec![Record(1, "bob"), Record(2, "zed"), Record(1, "robert")]
+            .into_iter()
+            .collect();
+        assert_eq!(names(&tree, b"b"), Vec::<&str>::new());
+        let mut extended = tree.clone();
+        extended.extend(vec![Record(2, "zachary"), Record(1, "bobby")]);
+        assert_eq!(names(&extended, b"b"), ["bobby"]);
+        assert_eq!(names(&extended, b"r"), Vec::<&str>::new());
+        assert_eq!(extended.search(b"").count(), 2);
+        extended.remove(&Record(2, ""));
+        assert_eq!(names(&extended, b""), ["bobby"]);
+    }
+
+    #[test]
+    fn every_token_must_prefix_some_key() {
+        let tree = make_tree!(
+            "jeffrey smith" => 1,
+            "jeff jones" => 2,
+            "jennifer smithers" => 3,
+        );
+        assert_eq!(
+            tree.search_tokens(b"jeff smith")
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["jeffrey smith"]
+        );
+        assert_eq!(
+            tree.search_tokens(b"smith  je")
+                .map(|r| r.0)
+                .collect::<Vec<_>>(),
+            ["jennifer smithers", "jeffrey smith"]
+        );
+        assert_eq!(
+            tree.search_tokens(b"jeff").map(|r| r.0).collect::<Vec<_>>(),
+            ["jeff jones", "jeffrey smith"]
+        );
+        assert_eq!(tree.search_tokens(b"jeff smithers").count(), 0);
+        assert_eq!(tree.search_tokens(b" ").count(), 3);
+    }
+
     #[test]
     fn multikey_items_example() {
         let tree = make_tree!(
@@ -255,4 +1834,21 @@ mod tests {
             ["hello world", "goodbye world"]
         );
     }
+
+    #[test]
+    fn unique_search_yields_each_item_once() {
+        let tree = make_tree!(
+            "hello world" => 1,
+            "goodbye world" => 0,
+            "world peace" => 2,
+        );
+        assert_eq!(
+            tree.search_unique(b"").map(|r| r.0).collect::<Vec<_>>(),
+            ["world peace", "hello world", "goodbye world"]
+        );
+        assert_eq!(
+            tree.search_unique(b"w").map(|r| r.0).collect::<Vec<_>>(),
+            ["world peace", "hello world", "goodbye world"]
+        );
+    }
 }
@@ -0,0 +1,288 @@
+use crate::{
+    binary::{self, NodeView},
+    Codec, FormatError, Scored, Symbol,
+};
+use std::{collections::BinaryHeap, marker::PhantomData};
+
+/// Searches a tree in the binary format without loading it. Nodes are read in place as the search
+/// reaches them, and items are handed back as their index and encoded bytes, leaving it to the
+/// caller to decode them or map them back to records of their own.
+pub struct MappedCompletionTree<D, S = i32, A = u8> {
+    data: D,
+    root_offset: Option<usize>,
+    _key: PhantomData<fn() -> (S, A)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedCompletion<'a, S = i32> {
+    pub index: usize,
+    pub item: &'a [u8],
+    pub score: S,
+}
+
+impl<D, S, A> MappedCompletionTree<D, S, A>
+where
+    D: AsRef<[u8]>,
+    S: Ord + Copy + Codec,
+    A: Symbol + Codec,
+{
+    /// Validates the header and checksum up front, so that a corrupt file is rejected here rather
+    /// than cutting a search short later on.
+    pub fn new(data: D) -> Result<Self, FormatError> {
+        let (header, body) = binary::parse_header(data.as_ref())?;
+        binary::item_count(body)?;
+        Ok(Self {
+            root_offset: header.root_offset,
+            data,
+            _key: PhantomData,
+        })
+    }
+
+    pub fn item_count(&self) -> usize {
+        binary::item_count(self.body()).expect("validated in new")
+    }
+
+    pub fn item(&self, index: usize) -> Option<&[u8]> {
+        binary::item_bytes(self.body(), index).ok()
+    }
+
+    /// Unlike `CompletionTree::search`, the prefix is taken as it is: the normalizer the tree was
+    /// built with isn't saved with it, so callers have to apply it to queries themselves.
+    pub fn search(&self, prefix: &[A]) -> impl Iterator<Item = MappedCompletion<'_, S>> {
+        let mut queue = BinaryHeap::new();
+        let start = self.root_offset.map(|root| self.descendent(root, prefix));
+        if let Some(Ok(Some((offset, max_score)))) = start {
+            queue.push(Scored {
+                item: MappedMarker::Node(offset),
+                score: max_score,
+            });
+        }
+        MappedIter { tree: self, queue }
+    }
+
+    fn body(&self) -> &[u8] {
+        &self.data.as_ref()[binary::HEADER_LEN..]
+    }
+
+    fn node(&self, offset: usize) -> Result<NodeView<'_, S>, FormatError> {
+        let input = self
+            .body()
+            .get(offset..)
+            .ok_or(FormatError::Corrupt("node offset out of range"))?;
+        NodeView::parse::<A>(input)
+    }
+
+    // Mirrors `Node::descendent`, returning the offset and max score of the shallowest node whose
+    // path starts with `path`.
+    fn descendent(
+        &self,
+        mut offset: usize,
+        mut path: &[A],
+    ) -> Result<Option<(usize, S)>, FormatError> {
+        let mut cur = self.node(offset)?;
+        while let Some(a) = path.first() {
+            let mut next = None;
+            for child in cur.children() {
+                let child = child_offset(offset, child)?;
+                let view = self.node(child)?;
+                if view.label::<A>().next().transpose()? == Some(*a) {
+                    next = Some((child, view));
+                    break;
+                }
+            }
+            let (child, view) = match next {
+                None => return Ok(None),
+                Some(next) => next,
+            };
+            let mut matched = 0;
+            for symbol in view.label::<A>() {
+                match path.get(matched) {
+                    None => return Ok(Some((child, view.max_score))),
+                    Some(&p) if p == symbol? => matched += 1,
+                    Some(_) => return Ok(None),
+                }
+            }
+            path = &path[matched..];
+            offset = child;
+            cur = view;
+        }
+        Ok(Some((offset, cur.max_score)))
+    }
+}
+
+// Children are written before their parents, so any other offset must be a forgery, and following
+// it could send a search around in circles.
+fn child_offset(parent: usize, child: Result<usize, FormatError>) -> Result<usize, FormatError> {
+    match child? {
+        child if child < parent => Ok(child),
+        _ => Err(FormatError::Corrupt("child offset out of order")),
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl<S, A> MappedCompletionTree<memmap2::Mmap, S, A>
+where
+    S: Ord + Copy + Codec,
+    A: Symbol + Codec,
+{
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Self, FormatError> {
+        let file = std::fs::File::open(path)?;
+        // Safety: the map is only sound for as long as nobody else modifies the file underneath
+        // it, which callers opening a static dictionary are expected to guarantee.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        Self::new(map)
+    }
+}
+
+enum MappedMarker {
+    Item(usize),
+    Node(usize),
+}
+struct MappedIter<'a, D, S, A> {
+    tree: &'a MappedCompletionTree<D, S, A>,
+    queue: BinaryHeap<Scored<MappedMarker, S>>,
+}
+impl<'a, D, S, A> MappedIter<'a, D, S, A>
+where
+    D: AsRef<[u8]>,
+    S: Ord + Copy + Codec,
+    A: Symbol + Codec,
+{
+    fn explore(&mut self, offset: usize) -> Result<(), FormatError> {
+        let node = self.tree.node(offset)?;
+        for item in node.items() {
+            let (index, score) = item?;
+            self.queue.push(Scored {
+                item: MappedMarker::Item(index),
+                score,
+            });
+        }
+        for child in node.children() {
+            let child = child_offset(offset, child)?;
+            self.queue.push(Scored {
+                item: MappedMarker::Node(child),
+                score: self.tree.node(child)?.max_score,
+            });
+        }
+        Ok(())
+    }
+}
+impl<'a, D, S, A> Iterator for MappedIter<'a, D, S, A>
+where
+    D: AsRef<[u8]>,
+    S: Ord + Copy + Codec,
+    A: Symbol + Codec,
+{
+    type Item = MappedCompletion<'a, S>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(cur) = self.queue.pop() {
+            let step = match cur.item {
+                MappedMarker::Item(index) => match self.tree.item(index) {
+                    Some(item) => {
+                        return Some(MappedCompletion {
+                            index,
+                            item,
+                            score: cur.score,
+                        })
+                    }
+                    None => Err(FormatError::Corrupt("item index out of range")),
+                },
+                MappedMarker::Node(offset) => self.explore(offset),
+            };
+            // Only a forged file with a valid checksum can get here. There's no way to report an
+            // error mid-iteration, so just stop.
+            if step.is_err() {
+                self.queue.clear();
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{make_tree, node, raw_file, Named, PREFIXES};
+    use crate::{Codec, CompletionTree, FormatError, MappedCompletionTree};
+
+    #[test]
+    fn mapped_search_matches_in_memory_search() {
+        let tree = make_tree();
+        let mapped = MappedCompletionTree::<_>::new(tree.to_bytes()).unwrap();
+        assert_eq!(mapped.item_count(), 8);
+        for prefix in PREFIXES {
+            let results = mapped
+                .search(prefix)
+                .map(|c| (Named::decode(&mut &c.item[..]).unwrap(), c.score))
+                .collect::<Vec<_>>();
+            let expected = tree
+                .search_scored(prefix)
+                .map(|c| (c.item.clone(), c.score))
+                .collect::<Vec<_>>();
+            assert_eq!(results, expected);
+        }
+    }
+
+    #[test]
+    fn items_are_addressed_by_index() {
+        let mapped = MappedCompletionTree::<_>::new(make_tree().to_bytes()).unwrap();
+        let hit = mapped.search(b"rubi").next().unwrap();
+        assert_eq!(mapped.item(hit.index), Some(hit.item));
+        assert_eq!(
+            Named::decode(&mut mapped.item(hit.index).unwrap()).unwrap(),
+            Named::new("rubicon river", 0)
+        );
+        assert_eq!(mapped.item(8), None);
+    }
+
+    #[test]
+    fn empty_trees_have_no_results() {
+        let tree = CompletionTree::<Named>::default();
+        let mapped = MappedCompletionTree::<_>::new(tree.to_bytes()).unwrap();
+        assert_eq!(mapped.search(b"").count(), 0);
+    }
+
+    #[test]
+    fn corrupt_files_are_rejected_up_front() {
+        let mut bytes = make_tree().to_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            MappedCompletionTree::<_>::new(bytes),
+            Err(FormatError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn cyclic_children_end_the_search() {
+        let items = [Named::new("a", 3)];
+        // A node that is its own child, and one whose child is the root.
+        for nodes in [
+            [
+                node(b"a", vec![(0, 3)], vec![0]),
+                node(b"", vec![], vec![0]),
+            ],
+            [
+                node(b"a", vec![(0, 3)], vec![1]),
+                node(b"", vec![], vec![0]),
+            ],
+        ] {
+            let mapped = MappedCompletionTree::<_>::new(raw_file(&items, &nodes)).unwrap();
+            assert_eq!(mapped.search(b"").count(), 0);
+            assert_eq!(mapped.search(b"aa").count(), 0);
+            assert!(CompletionTree::<Named>::from_bytes(&raw_file(&items, &nodes)).is_err());
+        }
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn open_maps_a_file() {
+        let path = std::env::temp_dir().join(format!("completion-trie-{}.bin", std::process::id()));
+        make_tree()
+            .write_to(std::fs::File::create(&path).unwrap())
+            .unwrap();
+        let mapped = MappedCompletionTree::<_>::open(&path).unwrap();
+        assert_eq!(mapped.search(b"rom").count(), 5);
+        drop(mapped);
+        std::fs::remove_file(path).unwrap();
+    }
+}
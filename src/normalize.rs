@@ -0,0 +1,89 @@
+/// Rewrites keys before they are stored and queries before they are looked up, so that symbols
+/// that ought to match are compared as equal. Normalizers work on whole sequences rather than
+/// single symbols, since folding text can change how many symbols there are.
+pub trait Normalizer<A>: Send + Sync {
+    fn normalize(&self, symbols: &[A]) -> Vec<A>;
+}
+
+/// Lowercases text, so that "alice" finds "Alice".
+pub struct CaseFold;
+impl Normalizer<u8> for CaseFold {
+    fn normalize(&self, symbols: &[u8]) -> Vec<u8> {
+        map_bytes(symbols, str::to_lowercase)
+    }
+}
+impl Normalizer<char> for CaseFold {
+    fn normalize(&self, symbols: &[char]) -> Vec<char> {
+        map_chars(symbols, str::to_lowercase)
+    }
+}
+
+/// Lowercases text, applies compatibility decomposition and strips diacritics, so that "alice"
+/// finds "Álice" and "ﬁle" finds "file". What is left is recomposed in NFKC.
+#[cfg(feature = "unicode")]
+pub struct UnicodeFold;
+#[cfg(feature = "unicode")]
+impl Normalizer<u8> for UnicodeFold {
+    fn normalize(&self, symbols: &[u8]) -> Vec<u8> {
+        map_bytes(symbols, unicode_fold)
+    }
+}
+#[cfg(feature = "unicode")]
+impl Normalizer<char> for UnicodeFold {
+    fn normalize(&self, symbols: &[char]) -> Vec<char> {
+        map_chars(symbols, unicode_fold)
+    }
+}
+#[cfg(feature = "unicode")]
+fn unicode_fold(s: &str) -> String {
+    use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+    let stripped: String = s.nfkd().filter(|&c| !is_combining_mark(c)).collect();
+    stripped.to_lowercase().nfkc().collect()
+}
+
+// Folds each run of valid UTF-8, passing any invalid bytes between them through untouched.
+fn map_bytes(symbols: &[u8], f: impl Fn(&str) -> String) -> Vec<u8> {
+    let mut out = Vec::with_capacity(symbols.len());
+    for chunk in symbols.utf8_chunks() {
+        out.extend_from_slice(f(chunk.valid()).as_bytes());
+        out.extend_from_slice(chunk.invalid());
+    }
+    out
+}
+fn map_chars(symbols: &[char], f: impl Fn(&str) -> String) -> Vec<char> {
+    f(&symbols.iter().collect::<String>()).chars().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CaseFold, Normalizer};
+
+    #[test]
+    fn case_folding() {
+        assert_eq!(CaseFold.normalize(&b"Alice SMITH"[..]), b"alice smith");
+        assert_eq!(CaseFold.normalize("ÉMILE".as_bytes()), "émile".as_bytes());
+        let chars: Vec<char> = "ÉMILE".chars().collect();
+        assert_eq!(
+            CaseFold.normalize(&chars),
+            "émile".chars().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_passes_through() {
+        assert_eq!(CaseFold.normalize(&b"AB\xffCD"[..]), b"ab\xffcd");
+    }
+
+    #[cfg(feature = "unicode")]
+    #[test]
+    fn unicode_folding() {
+        use crate::UnicodeFold;
+        let fold = |s: &str| String::from_utf8(UnicodeFold.normalize(s.as_bytes())).unwrap();
+        assert_eq!(fold("Álice"), "alice");
+        assert_eq!(fold("A\u{301}lice"), "alice");
+        assert_eq!(fold("ﬁle"), "file");
+        assert_eq!(fold("Ｒｏｍａ"), "roma");
+        let chars: Vec<char> = "Çà".chars().collect();
+        assert_eq!(UnicodeFold.normalize(&chars), ['c', 'a']);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{Completable, CompletionTree, Node, Scored, Symbol};
+use std::{collections::BinaryHeap, sync::Arc};
+
+/// A search that shares ownership of the tree rather than borrowing it, so that it can be sent to
+/// another thread or held across an `await`. The version of the tree it started on stays alive
+/// until it is dropped, which makes it a natural fit for `ConcurrentCompletionTree::snapshot`.
+/// Items are yielded as clones.
+pub struct OwnedCompletionIter<T, S = i32, A = u8> {
+    tree: Arc<CompletionTree<T, S, A>>,
+    queue: BinaryHeap<Scored<OwnedMarker<S, A>, S>>,
+}
+enum OwnedMarker<S, A> {
+    Item(u32),
+    Node(Arc<Node<u32, S, A>>),
+}
+
+impl<T, S, A> CompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    pub fn search_owned(self: &Arc<Self>, prefix: &[A]) -> OwnedCompletionIter<T, S, A> {
+        let mut queue = BinaryHeap::new();
+        let prefix = self.normalize(prefix);
+        if let Some((node, _)) = self.root.as_ref().and_then(|root| root.descendent(&prefix)) {
+            queue.push(Scored {
+                item: OwnedMarker::Node(Arc::clone(node)),
+                score: node.max_score,
+            });
+        }
+        OwnedCompletionIter {
+            tree: Arc::clone(self),
+            queue,
+        }
+    }
+}
+
+impl<T, S, A> Iterator for OwnedCompletionIter<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(cur) = self.queue.pop() {
+            match cur.item {
+                OwnedMarker::Item(id) => return Some(self.tree.items.get(id).clone()),
+                OwnedMarker::Node(node) => {
+                    for item in &node.items {
+                        self.queue.push(Scored {
+                            item: OwnedMarker::Item(item.item),
+                            score: item.score,
+                        });
+                    }
+                    for child in node.children.values() {
+                        self.queue.push(Scored {
+                            item: OwnedMarker::Node(Arc::clone(child)),
+                            score: child.max_score,
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::{make_tree, Word, PREFIXES};
+    use crate::{CompletionTree, ConcurrentCompletionTree};
+    use std::{sync::Arc, thread};
+
+    #[test]
+    fn owned_search_matches_search() {
+        let tree = make_tree();
+        let tree = Arc::new(tree);
+        for prefix in PREFIXES {
+            assert_eq!(
+                tree.search_owned(prefix).collect::<Vec<_>>(),
+                tree.search(prefix).cloned().collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn owned_search_outlives_the_tree() {
+        let mut tree = CompletionTree::default();
+        tree.put(Word("alpha", 1));
+        tree.put(Word("alpine", 2));
+        let mut iter = Arc::new(tree).search_owned(b"al");
+        assert_eq!(iter.next(), Some(Word("alpine", 2)));
+        let rest = thread::spawn(move || iter.collect::<Vec<_>>());
+        assert_eq!(rest.join().unwrap(), [Word("alpha", 1)]);
+    }
+
+    #[test]
+    fn owned_search_keeps_its_snapshot() {
+        let tree = ConcurrentCompletionTree::new();
+        tree.write(|tree| tree.put(Word("alpha", 1)));
+        let iter = tree.snapshot().search_owned(b"al");
+        tree.write(|tree| {
+            tree.remove(&Word("alpha", 1));
+            tree.put(Word("alpine", 2));
+        });
+        assert_eq!(iter.collect::<Vec<_>>(), [Word("alpha", 1)]);
+        let iter = tree.snapshot().search_owned(b"al");
+        assert_eq!(iter.collect::<Vec<_>>(), [Word("alpine", 2)]);
+    }
+}
@@ -0,0 +1,106 @@
+use crate::{common_prefix_len, Completable, CompletionTree, Key, Node, Symbol};
+use rayon::prelude::*;
+use std::{collections::BTreeMap, sync::Arc};
+
+impl<T, S, A> CompletionTree<T, S, A>
+where
+    T: Completable<S, A> + Send + Sync,
+    S: Ord + Copy + Send + Sync,
+    A: Symbol + Send + Sync,
+{
+    /// Builds the same tree as `collect`, but with keys computed, sorted and placed on rayon's
+    /// thread pool; only handing out item ids happens on the calling thread. Keys are split up by
+    /// their first symbol, and since no two of those groups share a node below the root, each one
+    /// is built into a subtree of its own concurrently.
+    pub fn par_from_iter<I: IntoParallelIterator<Item = T>>(iter: I) -> Self {
+        let items: Vec<(T, Vec<Key<S, A>>)> = iter
+            .into_par_iter()
+            .map(|item| {
+                let keys = item.keys();
+                (item, keys)
+            })
+            .collect();
+        let mut tree = Self::default();
+        let mut keys = tree.insert_all(items);
+        keys.par_sort_by(|a, b| a.0.cmp(&b.0));
+
+        let max_score = match keys.iter().map(|key| key.1).max() {
+            None => return tree,
+            Some(max_score) => max_score,
+        };
+        let exact = keys.iter().take_while(|key| key.0.is_empty()).count();
+        let (exact, mut rest) = keys.split_at(exact);
+        let mut root = Node::new(Vec::new(), max_score);
+        root.put_sorted(exact, 0);
+
+        let mut groups = Vec::new();
+        while let Some(a) = rest.first().map(|key| key.0[0]) {
+            let len = rest.iter().take_while(|key| key.0[0] == a).count();
+            let (group, tail) = rest.split_at(len);
+            rest = tail;
+            groups.push((a, group));
+        }
+        let children: Vec<_> = groups
+            .into_par_iter()
+            .map(|(a, group)| {
+                let shared = common_prefix_len(&group[0].0, &group[group.len() - 1].0);
+                let max_score = group.iter().map(|key| key.1).max();
+                let mut child = Node::new(
+                    group[0].0[..shared].to_vec(),
+                    max_score.expect("non-empty group"),
+                );
+                child.put_sorted(group, shared);
+                (a, Arc::new(child))
+            })
+            .collect();
+        root.children = children.into_iter().collect::<BTreeMap<_, _>>();
+        tree.root = Some(Arc::new(root));
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Completable, CompletionTree, Key, KeyKind};
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+    struct Counter(u32);
+    impl Completable for Counter {
+        fn keys(&self) -> Vec<Key> {
+            let name = format!("{:06}", self.0);
+            vec![
+                Key {
+                    symbols: name.as_bytes().to_vec(),
+                    score: (self.0 % 97) as i32,
+                    kind: KeyKind::Primary,
+                },
+                Key {
+                    symbols: name.as_bytes()[3..].to_vec(),
+                    score: -((self.0 % 13) as i32),
+                    kind: KeyKind::WordSuffix,
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn parallel_construction_matches_collect() {
+        let counters: Vec<_> = (0..5_000).map(Counter).collect();
+        let parallel = CompletionTree::par_from_iter(counters.clone());
+        let sequential: CompletionTree<_> = counters.into_iter().collect();
+        for prefix in [&b""[..], b"0", b"00", b"001", b"0042", b"1", b"9", b"x"] {
+            assert_eq!(
+                parallel.search_scored(prefix).collect::<Vec<_>>(),
+                sequential.search_scored(prefix).collect::<Vec<_>>()
+            );
+        }
+        assert!(parallel.items.iter().eq(sequential.items.iter()));
+    }
+
+    #[test]
+    fn parallel_construction_of_nothing_is_empty() {
+        let tree = CompletionTree::<Counter>::par_from_iter(Vec::new());
+        assert!(tree.root.is_none());
+        assert_eq!(tree.search(b"").count(), 0);
+    }
+}
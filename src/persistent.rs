@@ -0,0 +1,138 @@
+use crate::{Completable, Completion, CompletionIter, Node, Symbol};
+use std::{collections::HashSet, sync::Arc};
+
+/// A tree whose versions share structure. Cloning one is constant-time, and a change to either
+/// copy only duplicates the nodes along the paths of the keys it touches, leaving every other
+/// version queryable as it was.
+///
+/// There is no index of stored items, so `remove` finds an item by the keys it reports now,
+/// which must be the keys it was put under.
+pub struct PersistentCompletionTree<T, S = i32, A = u8> {
+    root: Option<Arc<Node<T, S, A>>>,
+}
+impl<T, S, A> Clone for PersistentCompletionTree<T, S, A> {
+    fn clone(&self) -> Self {
+        Self {
+            root: self.root.clone(),
+        }
+    }
+}
+impl<T, S, A> Default for PersistentCompletionTree<T, S, A> {
+    fn default() -> Self {
+        Self { root: None }
+    }
+}
+impl<T, S, A> PersistentCompletionTree<T, S, A>
+where
+    T: Completable<S, A>,
+    S: Ord + Copy,
+    A: Symbol,
+{
+    pub fn put(&mut self, item: T) {
+        for key in item.keys() {
+            let root = self
+                .root
+                .get_or_insert_with(|| Arc::new(Node::new(Vec::new(), key.score)));
+            Arc::make_mut(root).put_key(key, item.clone());
+        }
+    }
+
+    pub fn remove(&mut self, item: &T) {
+        if let Some(root) = self.root.as_mut() {
+            let root = Arc::make_mut(root);
+            for key in item.keys() {
+                root.remove_key(&key.symbols, item);
+            }
+            if root.is_empty() {
+                self.root = None;
+            }
+        }
+    }
+
+    pub fn search(&self, prefix: &[A]) -> impl Iterator<Item = &T> {
+        let iter = match self.root.as_ref().and_then(|root| root.descendent(prefix)) {
+            None => CompletionIter::empty(),
+            Some((node, _)) => CompletionIter::from(node, ()),
+        };
+        iter.map(|(item, _, ())| item)
+    }
+
+    pub fn search_scored(&self, prefix: &[A]) -> impl Iterator<Item = Completion<'_, T, S, A>> {
+        let iter = match self.root.as_ref().and_then(|root| root.descendent(prefix)) {
+            None => CompletionIter::empty(),
+            Some((node, rest)) => CompletionIter::from(node, [prefix, rest].concat()),
+        };
+        iter.map(|(item, score, key)| Completion { item, score, key })
+    }
+
+    pub fn search_unique(&self, prefix: &[A]) -> impl Iterator<Item = &T> {
+        let mut seen = HashSet::new();
+        self.search(prefix).filter(move |item| seen.insert(*item))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::Word;
+    use crate::{Node, PersistentCompletionTree};
+    use std::sync::Arc;
+
+    fn shared_nodes(a: &Arc<Node<Word, i32, u8>>, b: &Arc<Node<Word, i32, u8>>) -> usize {
+        if Arc::ptr_eq(a, b) {
+            return 1;
+        }
+        a.children
+            .iter()
+            .filter_map(|(first, child)| Some((child, b.children.get(first)?)))
+            .map(|(a, b)| shared_nodes(a, b))
+            .sum()
+    }
+
+    #[test]
+    fn old_versions_are_unaffected_by_changes() {
+        let mut v1 = PersistentCompletionTree::default();
+        v1.put(Word("romane", 1));
+        v1.put(Word("romulus", 3));
+        let mut v2 = v1.clone();
+        v2.put(Word("rubens", 4));
+        v2.remove(&Word("romulus", 3));
+        let mut v3 = v2.clone();
+        v3.put(Word("romulus", 5));
+
+        let search = |tree: &PersistentCompletionTree<Word>| -> Vec<Word> {
+            tree.search(b"r").cloned().collect()
+        };
+        assert_eq!(search(&v1), [Word("romulus", 3), Word("romane", 1)]);
+        assert_eq!(search(&v2), [Word("rubens", 4), Word("romane", 1)]);
+        assert_eq!(
+            search(&v3),
+            [Word("romulus", 5), Word("rubens", 4), Word("romane", 1)]
+        );
+        assert_eq!(v2.search_scored(b"ro").next().unwrap().score, 1);
+    }
+
+    #[test]
+    fn changes_only_copy_their_own_path() {
+        let mut v1 = PersistentCompletionTree::default();
+        for word in ["apple", "apricot", "banana", "blueberry", "cherry"] {
+            v1.put(Word(word, 0));
+        }
+        let mut v2 = v1.clone();
+        v2.put(Word("avocado", 0));
+        let (a, b) = (v1.root.as_ref().unwrap(), v2.root.as_ref().unwrap());
+        // The root and "a" were copied, but "b" and "cherry" are still shared.
+        assert!(!Arc::ptr_eq(a, b));
+        assert_eq!(shared_nodes(a, b), 2);
+        assert!(Arc::ptr_eq(&a.children[&b'b'], &b.children[&b'b']));
+    }
+
+    #[test]
+    fn removing_everything_empties_the_tree() {
+        let mut tree = PersistentCompletionTree::default();
+        tree.put(Word("rom", 1));
+        let before = tree.clone();
+        tree.remove(&Word("rom", 1));
+        assert!(tree.root.is_none());
+        assert_eq!(before.search(b"").count(), 1);
+    }
+}
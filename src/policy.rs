@@ -0,0 +1,34 @@
+use crate::{KeyKind, SaturatingSub};
+
+/// Turns the score an item gives a key into the score the tree stores it under, depending on what
+/// kind of key it is. Like normalization, this happens as keys are stored.
+pub trait ScoringPolicy<S>: Send + Sync {
+    fn score(&self, kind: KeyKind, score: S) -> S;
+}
+impl<S, F> ScoringPolicy<S> for F
+where
+    F: Fn(KeyKind, S) -> S + Send + Sync,
+{
+    fn score(&self, kind: KeyKind, score: S) -> S {
+        self(kind, score)
+    }
+}
+
+/// Leaves primary keys as they are and subtracts a fixed penalty from every other kind, stopping
+/// at the lowest score.
+pub struct Penalties<S> {
+    pub alias: S,
+    pub word_suffix: S,
+}
+impl<S> ScoringPolicy<S> for Penalties<S>
+where
+    S: SaturatingSub + Copy + Send + Sync,
+{
+    fn score(&self, kind: KeyKind, score: S) -> S {
+        match kind {
+            KeyKind::Primary => score,
+            KeyKind::Alias => score.saturating_sub(self.alias),
+            KeyKind::WordSuffix => score.saturating_sub(self.word_suffix),
+        }
+    }
+}
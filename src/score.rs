@@ -0,0 +1,115 @@
+use std::{
+    cmp::Ordering,
+    hash::{Hash, Hasher},
+    ops::{Add, Sub},
+};
+
+/// Floats are only partially ordered, so they can't be used as scores directly. `TotalOrd` orders
+/// them with `total_cmp`, which puts NaNs at the extremes rather than refusing to compare them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TotalOrd<F>(pub F);
+
+macro_rules! impl_total_ord {
+    ($($float:ty),*) => {$(
+        impl Ord for TotalOrd<$float> {
+            fn cmp(&self, other: &Self) -> Ordering {
+                self.0.total_cmp(&other.0)
+            }
+        }
+        impl PartialOrd for TotalOrd<$float> {
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+        impl PartialEq for TotalOrd<$float> {
+            fn eq(&self, other: &Self) -> bool {
+                self.cmp(other) == Ordering::Equal
+            }
+        }
+        impl Eq for TotalOrd<$float> {}
+        impl Hash for TotalOrd<$float> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.0.to_bits().hash(state);
+            }
+        }
+        impl Add for TotalOrd<$float> {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                TotalOrd(self.0 + rhs.0)
+            }
+        }
+        impl SaturatingAdd for TotalOrd<$float> {
+            fn saturating_add(self, rhs: Self) -> Self {
+                // Floats already stop at infinity.
+                self + rhs
+            }
+        }
+        impl SaturatingSub for TotalOrd<$float> {
+            fn saturating_sub(self, rhs: Self) -> Self {
+                self - rhs
+            }
+        }
+        impl Sub for TotalOrd<$float> {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                TotalOrd(self.0 - rhs.0)
+            }
+        }
+        impl From<$float> for TotalOrd<$float> {
+            fn from(f: $float) -> Self {
+                TotalOrd(f)
+            }
+        }
+    )*};
+}
+impl_total_ord!(f32, f64);
+
+/// Addition that stops at the largest score rather than overflowing, for raising an upper bound on
+/// scores without wrapping it around past the others.
+pub trait SaturatingAdd {
+    fn saturating_add(self, rhs: Self) -> Self;
+}
+/// Subtraction that stops at the smallest score rather than overflowing, for penalizing a score
+/// without wrapping it around past the others.
+pub trait SaturatingSub {
+    fn saturating_sub(self, rhs: Self) -> Self;
+}
+macro_rules! impl_saturating {
+    ($($int:ty),*) => {$(
+        impl SaturatingAdd for $int {
+            fn saturating_add(self, rhs: Self) -> Self {
+                <$int>::saturating_add(self, rhs)
+            }
+        }
+        impl SaturatingSub for $int {
+            fn saturating_sub(self, rhs: Self) -> Self {
+                <$int>::saturating_sub(self, rhs)
+            }
+        }
+    )*};
+}
+impl_saturating!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+#[cfg(test)]
+mod tests {
+    use crate::TotalOrd;
+
+    #[test]
+    fn floats_are_totally_ordered() {
+        let mut scores = vec![
+            TotalOrd(0.5f32),
+            TotalOrd(-1.0),
+            TotalOrd(f32::INFINITY),
+            TotalOrd(0.25),
+        ];
+        scores.sort();
+        assert_eq!(
+            scores.into_iter().map(|s| s.0).collect::<Vec<_>>(),
+            [-1.0, 0.25, 0.5, f32::INFINITY]
+        );
+        assert_eq!(TotalOrd(0.0f64), TotalOrd(0.0));
+        assert_ne!(TotalOrd(0.0f64), TotalOrd(-0.0));
+    }
+}
@@ -0,0 +1,20 @@
+/// Anything a key can be spelled with. Separators split a query into tokens for `search_tokens`;
+/// alphabets without a natural separator (like token IDs) can leave the default in place.
+pub trait Symbol: Ord + Copy {
+    fn is_separator(&self) -> bool {
+        false
+    }
+}
+impl Symbol for u8 {
+    fn is_separator(&self) -> bool {
+        self.is_ascii_whitespace()
+    }
+}
+impl Symbol for char {
+    fn is_separator(&self) -> bool {
+        self.is_whitespace()
+    }
+}
+impl Symbol for u16 {}
+impl Symbol for u32 {}
+impl Symbol for u64 {}